@@ -0,0 +1,304 @@
+use std::fmt;
+use std::process::Command;
+use std::str::FromStr;
+
+use crate::client::Table;
+use crate::CommandResult;
+
+/// Plugin return codes, as understood by Nagios and compatible monitoring systems.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Status {
+    Ok = 0,
+    Warning = 1,
+    Critical = 2,
+    Unknown = 3,
+}
+
+impl Status {
+    /// How bad a status is, for picking the worst of several. Unknown ranks between OK and WARNING.
+    fn severity(&self) -> u8 {
+        match self {
+            Status::Ok => 0,
+            Status::Unknown => 1,
+            Status::Warning => 2,
+            Status::Critical => 3,
+        }
+    }
+
+    pub fn worst(self, other: Status) -> Status {
+        if other.severity() > self.severity() { other } else { self }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            Status::Ok => "OK",
+            Status::Warning => "WARNING",
+            Status::Critical => "CRITICAL",
+            Status::Unknown => "UNKNOWN",
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Operator {
+    fn apply(&self, left: u16, right: u16) -> bool {
+        match self {
+            Operator::Eq => left == right,
+            Operator::Ne => left != right,
+            Operator::Gt => left > right,
+            Operator::Ge => left >= right,
+            Operator::Lt => left < right,
+            Operator::Le => left <= right,
+        }
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            Operator::Eq => "==",
+            Operator::Ne => "!=",
+            Operator::Gt => ">",
+            Operator::Ge => ">=",
+            Operator::Lt => "<",
+            Operator::Le => "<=",
+        })
+    }
+}
+
+fn table_prefix(table: Table) -> &'static str {
+    match table {
+        Table::Coils => "co",
+        Table::DiscreteInputs => "di",
+        Table::InputRegisters => "ir",
+        Table::HoldingRegisters => "hr",
+    }
+}
+
+fn parse_number(s: &str) -> Result<u16, String> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse::<u16>(),
+    }.map_err(|e| format!("invalid number '{}': {}", s, e))
+}
+
+fn parse_value(s: &str) -> Result<u16, String> {
+    match s {
+        "true" => Ok(1),
+        "false" => Ok(0),
+        _ => parse_number(s),
+    }
+}
+
+/// Parse a reference to a single address, e.g. `hr:100`. Tables are referred to as co, di, ir and hr.
+pub fn parse_reference(s: &str) -> Result<(Table, u16), String> {
+    let (prefix, address) = s.split_once(':')
+        .ok_or_else(|| "expected <table>:<address>, e.g. hr:100".to_string())?;
+    let table = [Table::Coils, Table::DiscreteInputs, Table::InputRegisters, Table::HoldingRegisters]
+        .into_iter()
+        .find(|&table| table_prefix(table) == prefix.trim())
+        .ok_or_else(|| "table must be one of co, di, ir, hr".to_string())?;
+    let address = parse_number(address.trim())?;
+    Ok((table, address))
+}
+
+/// A comparison of a single address against a constant, e.g. `hr:100 > 350` or `co:12 == true`.
+/// Tables are referred to as co, di, ir and hr. Values may be decimal, hex with a 0x prefix, or true/false.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Condition {
+    pub table: Table,
+    pub address: u16,
+    pub operator: Operator,
+    pub value: u16,
+    text: String,
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Condition, Self::Err> {
+        // Two-character operators go first so that `>=` isn't read as `>`.
+        const OPERATORS: [(&str, Operator); 6] = [
+            ("==", Operator::Eq),
+            ("!=", Operator::Ne),
+            (">=", Operator::Ge),
+            ("<=", Operator::Le),
+            (">", Operator::Gt),
+            ("<", Operator::Lt),
+        ];
+        let (left, operator, right) = OPERATORS.iter()
+            .find_map(|(token, operator)| s.split_once(token).map(|(l, r)| (l.trim(), *operator, r.trim())))
+            .ok_or_else(|| format!("invalid condition '{}': expected one of ==, !=, >, >=, <, <=", s))?;
+
+        let (table, address) = parse_reference(left).map_err(|e| format!("invalid condition '{}': {}", s, e))?;
+        let value = parse_value(right).map_err(|e| format!("invalid condition '{}': {}", s, e))?;
+
+        Ok(Condition { table, address, operator, value, text: s.trim().to_string() })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl Condition {
+    /// Whether the condition holds for a value of its address.
+    pub fn holds(&self, value: u16) -> bool {
+        self.operator.apply(value, self.value)
+    }
+
+    /// Evaluate the condition against a read result, returning the value it was compared with and whether it held.
+    /// `read` is the table the result was read from and the address it starts at, as rows are numbered from the start
+    /// of the read. Results that don't cover the condition's table and address can't be evaluated.
+    pub fn evaluate(&self, read: Option<(Table, u16)>, result: &CommandResult) -> Result<(String, bool), String> {
+        let first = match read {
+            Some((table, first)) if table == self.table => first,
+            _ => return Err(format!("{} is not covered by this read", self)),
+        };
+        let value = self.address.checked_sub(first)
+            .and_then(|offset| result.rows.get(usize::from(offset)))
+            .and_then(|row| row.get(1))
+            .ok_or_else(|| format!("{}:{} is not covered by this read", table_prefix(self.table), self.address))?;
+        let parsed = parse_value(value)?;
+        Ok((value.clone(), self.holds(parsed)))
+    }
+}
+
+/// Conditions to check read results against, and what to do when they are violated.
+#[derive(Clone, Debug, Default)]
+pub struct Alarms {
+    pub warning: Vec<Condition>,
+    pub critical: Vec<Condition>,
+    pub hook: Option<String>,
+}
+
+impl Alarms {
+    pub fn is_empty(&self) -> bool {
+        self.warning.is_empty() && self.critical.is_empty()
+    }
+
+    /// Check a read result against every condition, printing a summary line to stderr.
+    pub fn check(&self, read: Option<(Table, u16)>, result: &CommandResult) -> Status {
+        let mut status = Status::Ok;
+        let mut details: Vec<String> = vec![];
+        let levels = [(Status::Warning, &self.warning), (Status::Critical, &self.critical)];
+        for (level, conditions) in levels {
+            for condition in conditions.iter() {
+                match condition.evaluate(read, result) {
+                    Ok((value, true)) => {
+                        status = status.worst(level);
+                        details.push(format!("{} (value {})", condition, value));
+                    },
+                    Ok((_, false)) => {},
+                    Err(e) => {
+                        status = status.worst(Status::Unknown);
+                        details.push(e);
+                    },
+                }
+            }
+        }
+
+        if details.is_empty() {
+            eprintln!("{} - all conditions passed", status);
+        } else {
+            eprintln!("{} - {}", status, details.join("; "));
+        }
+        status
+    }
+
+    /// Run the hook, if any, passing the status and the violated conditions in the environment.
+    pub fn run_hook(&self, status: Status, read: Option<(Table, u16)>, result: &CommandResult) {
+        let hook = match &self.hook {
+            Some(hook) => hook,
+            None => return,
+        };
+        let violations: Vec<String> = self.warning.iter()
+            .chain(self.critical.iter())
+            .filter(|condition| matches!(condition.evaluate(read, result), Ok((_, true))))
+            .map(|condition| condition.to_string())
+            .collect();
+        let outcome = Command::new("sh")
+            .arg("-c")
+            .arg(hook)
+            .env("MBC_STATUS", status.to_string())
+            .env("MBC_VIOLATIONS", violations.join("; "))
+            .status();
+        match outcome {
+            Ok(exit) if !exit.success() => eprintln!("hook `{}` exited with {}", hook, exit),
+            Err(e) => eprintln!("failed to run hook `{}`: {}", hook, e),
+            _ => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The result of reading two holding registers from 100, as the read command lays it out.
+    fn holding_registers() -> CommandResult {
+        CommandResult {
+            columns: vec!["address".to_string(), "value".to_string()],
+            rows: vec![
+                vec!["0".to_string(), "0x15e".to_string()],
+                vec!["1".to_string(), "0x01".to_string()],
+            ],
+        }
+    }
+
+    #[test]
+    fn parses_conditions() {
+        let condition: Condition = "hr:100 >= 0x15E".parse().unwrap();
+        assert_eq!((condition.table, condition.address, condition.operator, condition.value), (Table::HoldingRegisters, 100, Operator::Ge, 350));
+        let condition: Condition = " co:12==true ".parse().unwrap();
+        assert_eq!((condition.table, condition.address, condition.operator, condition.value), (Table::Coils, 12, Operator::Eq, 1));
+        assert_eq!(condition.to_string(), "co:12==true");
+    }
+
+    #[test]
+    fn rejects_invalid_conditions() {
+        assert!("hr:100 = 5".parse::<Condition>().is_err());
+        assert!("xx:100 > 5".parse::<Condition>().is_err());
+        assert!("hr100 > 5".parse::<Condition>().is_err());
+        assert!("hr:100 > 70000".parse::<Condition>().is_err());
+    }
+
+    #[test]
+    fn evaluates_by_absolute_address() {
+        let condition: Condition = "hr:100 > 300".parse().unwrap();
+        assert_eq!(condition.evaluate(Some((Table::HoldingRegisters, 100)), &holding_registers()), Ok(("0x15e".to_string(), true)));
+        let condition: Condition = "hr:101 != 1".parse().unwrap();
+        assert_eq!(condition.evaluate(Some((Table::HoldingRegisters, 100)), &holding_registers()), Ok(("0x01".to_string(), false)));
+    }
+
+    #[test]
+    fn reports_conditions_the_read_doesnt_cover() {
+        // The offset column isn't mistaken for the address.
+        let condition: Condition = "hr:0 > 300".parse().unwrap();
+        assert!(condition.evaluate(Some((Table::HoldingRegisters, 100)), &holding_registers()).is_err());
+        let condition: Condition = "hr:102 > 300".parse().unwrap();
+        assert!(condition.evaluate(Some((Table::HoldingRegisters, 100)), &holding_registers()).is_err());
+        let condition: Condition = "ir:100 > 300".parse().unwrap();
+        assert!(condition.evaluate(Some((Table::HoldingRegisters, 100)), &holding_registers()).is_err());
+    }
+
+    #[test]
+    fn worst_status_ranks_unknown_below_warning() {
+        assert_eq!(Status::Ok.worst(Status::Unknown), Status::Unknown);
+        assert_eq!(Status::Unknown.worst(Status::Warning), Status::Warning);
+        assert_eq!(Status::Critical.worst(Status::Warning), Status::Critical);
+    }
+}
@@ -1,5 +1,9 @@
+use std::fs;
+use std::str::FromStr;
+use std::time::Duration;
+use anyhow::{Context, Error};
 use clap::{Parser, Subcommand};
-use crate::{custom, read, write, uri, output::OutputPlugin};
+use crate::{custom, decode, file, gateway, mei, monitor, proxy, read, scan, serve, session, sniff, watch, write, uri, output::OutputPlugin};
 
 #[derive(Clone, Parser, Debug)]
 #[clap(author="Michael Torres", about="A CLI for making Modbus requests")]
@@ -8,9 +12,27 @@ pub struct Args {
     /// 
     /// For rtu URIs, the port is the bitrate (baud) for the serial interface. Default 9600
     /// For tcp URIs, default port is 502
-    /// Examples: rtu:///dev/ttyUSB0, tcp://127.0.0.1:502
+    /// Examples: rtu:///dev/ttyUSB0, rtu:///dev/ttyUSB0:19200, tcp://127.0.0.1:502
+    /// For serve, the address to listen on or the serial device to answer on
+    /// May be left out when devices are given with --target or --targets-file
     #[clap(value_parser, verbatim_doc_comment)]
-    pub uri: uri::ModbusUri,
+    pub uri: Option<uri::ModbusUri>,
+
+    /// Additional device to run a read against. May be repeated to read from many devices at once
+    #[clap(long, value_parser)]
+    pub target: Vec<uri::ModbusUri>,
+
+    /// File listing devices to run a read against, one URI per line. Blank lines and lines starting with # are ignored
+    #[clap(long, value_parser)]
+    pub targets_file: Option<String>,
+
+    /// Most devices to talk to at once when reading from several or scanning a network
+    #[clap(long, value_parser = clap::value_parser!(u16).range(1..), default_value_t = 8)]
+    pub parallelism: u16,
+
+    /// Give up on a device that hasn't answered within this time when reading from several, e.g. 5s
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "10s")]
+    pub device_timeout: Duration,
 
     /// Local terminal ID for RTU communication.
     #[clap(value_parser, default_value_t = 42)]
@@ -24,13 +46,73 @@ pub struct Args {
     #[clap(long, value_enum, default_value_t = OutputPlugin::Tsv)]
     pub output_plugin: OutputPlugin,
 
+    /// Append every request and response sent to the device to this JSON lines file, for use with replay
+    #[clap(long, value_parser)]
+    pub record: Option<String>,
+
+    /// Write every frame sent to and received from the device to this pcap file, for opening in Wireshark
+    ///
+    /// RTU frames are written with link type User 0 (147), which Wireshark only decodes once it is told to:
+    /// Edit > Preferences > Protocols > DLT_USER > Edit, add an entry with DLT "User 0 (DLT=147)",
+    /// payload protocol "mbrtu", header size 0 and trailer size 0, then OK and reopen the capture.
+    #[clap(long, value_parser, verbatim_doc_comment)]
+    pub pcap: Option<String>,
+
     #[clap(subcommand)]
     pub action: Action,
 }
 
+impl Args {
+    /// Every device the action should run against, from the URI argument, --target and --targets-file.
+    pub fn targets(&self) -> Result<Vec<uri::ModbusUri>, Error> {
+        let mut targets: Vec<uri::ModbusUri> = self.uri.iter().cloned().collect();
+        targets.extend(self.target.iter().cloned());
+        if let Some(file_name) = &self.targets_file {
+            let contents = fs::read_to_string(file_name)
+                .with_context(|| format!("failed to read '{}'", file_name))?;
+            for (i, line) in contents.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let target = uri::ModbusUri::from_str(line)
+                    .with_context(|| format!("invalid target on line {} of '{}'", i + 1, file_name))?;
+                targets.push(target);
+            }
+        }
+        Ok(targets)
+    }
+}
+
 #[derive(Clone, Debug, Subcommand)]
 pub enum Action {
     Read(read::args::ReadArgs),
     Custom(custom::CustomArgs),
     Write(write::args::WriteArgs),
-}
\ No newline at end of file
+    File(file::args::FileArgs),
+    /// Read all basic, regular and extended device identification objects
+    Identify,
+    Mei(mei::MeiArgs),
+    Watch(watch::args::WatchArgs),
+    Monitor(monitor::args::MonitorArgs),
+    Scan(scan::args::ScanArgs),
+    Serve(serve::args::ServeArgs),
+    Gateway(gateway::args::GatewayArgs),
+    Proxy(proxy::args::ProxyArgs),
+    Replay(session::args::ReplayArgs),
+    Decode(decode::args::DecodeArgs),
+    Sniff(sniff::args::SniffArgs),
+}
+
+impl Action {
+    /// Whether the action talks to the device given by the URI through a client connection, which --record and
+    /// --pcap capture. Servers, relays and standalone scans handle their traffic themselves.
+    pub fn connects(&self) -> bool {
+        match self {
+            Action::Scan(scan_args) => scan_args.function.needs_connection(),
+            Action::Serve(_) | Action::Gateway(_) | Action::Proxy(_) | Action::Decode(_) | Action::Sniff(_) => false,
+            Action::Replay(replay_args) => !replay_args.serve,
+            _ => true,
+        }
+    }
+}
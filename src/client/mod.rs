@@ -2,22 +2,65 @@ use core::fmt;
 use std::collections::VecDeque;
 use std::io::{Error, ErrorKind};
 use std::net::SocketAddr;
-use tokio_serial;
-use tokio_modbus::prelude::{Reader, Request, Response, rtu, tcp, Writer};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context as TaskContext, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_modbus::prelude::{Reader, Request, Response, rtu, tcp, Slave, SlaveContext, Writer};
 pub use tokio_modbus::client::{Client, Context};
 use async_trait::async_trait;
 use byteorder::{BigEndian, ReadBytesExt};
 use clap::ValueEnum;
+use tokio_serial::{ClearBuffer, SerialPort, SerialStream};
 
-use crate::args::Args;
-use crate::uri::Proto;
+use crate::uri::{ModbusUri, Proto};
 
 const READ_FILE_RECORD: u8 = 0x14;
 const READ_FIFO_QUEUE: u8 = 0x18;
-const READ_DEVICE_IDENTIFICATION: u8 = 0x2B;
+const ENCAPSULATED_INTERFACE_TRANSPORT: u8 = 0x2B;
 const READ_SERVER_IDENTIFICATION: u8 = 0x11;
 const WRITE_FILE_RECORD: u8 = 0x15;
-const MEI_CODE: u8 = 0x0E;
+/// A FIFO queue holds at most 31 registers.
+const MAX_FIFO_COUNT: u16 = 31;
+
+/// Exception codes, with the descriptions tokio-modbus reports them with.
+const EXCEPTIONS: [(u8, &str); 9] = [
+    (0x01, "Illegal function"),
+    (0x02, "Illegal data address"),
+    (0x03, "Illegal data value"),
+    (0x04, "Server device failure"),
+    (0x05, "Acknowledge"),
+    (0x06, "Server device busy"),
+    (0x08, "Memory parity error"),
+    (0x0A, "Gateway path unavailable"),
+    (0x0B, "Gateway target device failed to respond"),
+];
+
+pub const ILLEGAL_FUNCTION: u8 = 0x01;
+pub const ILLEGAL_DATA_ADDRESS: u8 = 0x02;
+pub const ILLEGAL_DATA_VALUE: u8 = 0x03;
+pub const SERVER_DEVICE_FAILURE: u8 = 0x04;
+pub const SERVER_DEVICE_BUSY: u8 = 0x06;
+
+/// The exception code a device answered with, if the error is an exception response.
+/// tokio-modbus doesn't expose its exception type, so the code is recovered from the error message.
+pub fn exception_code(err: &Error) -> Option<u8> {
+    if err.kind() != ErrorKind::Other {
+        return None;
+    }
+    let message = err.get_ref()?.to_string();
+    EXCEPTIONS.iter()
+        .find(|(_, description)| message.ends_with(description))
+        .map(|&(code, _)| code)
+}
+
+/// Human-readable name of an exception code.
+pub fn exception_name(code: u8) -> String {
+    match EXCEPTIONS.iter().find(|&&(c, _)| c == code) {
+        Some((_, description)) => format!("{} ({:#04x})", description, code),
+        None => format!("Unknown exception ({:#04x})", code),
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FileRecord {
@@ -38,15 +81,167 @@ impl TryFrom<Vec<u8>> for FileRecord {
         let file_resp_len = data[1];
         let ref_type = data[2];
 
+        // Record data starts after the three length/type bytes and must be a whole number of words.
+        if !(data.len() - 3).is_multiple_of(2) {
+            return Err(Error::new(ErrorKind::InvalidData, format!("record data len {}, want an even number of bytes", data.len() - 3)))
+        }
         let mut record_data: Vec<u16> = vec![];
-        for i in (2..data.len()).step_by(2) {
-            record_data.push((&data[i..i+1]).read_u16::<BigEndian>().unwrap());
+        for i in (3..data.len()).step_by(2) {
+            record_data.push((&data[i..i+2]).read_u16::<BigEndian>()?);
         }
 
         Ok(FileRecord{resp_data_len, file_resp_len, ref_type, record_data})
     }
 }
 
+/// The four Modbus data tables
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, ValueEnum)]
+pub enum Table {
+    Coils,
+    DiscreteInputs,
+    InputRegisters,
+    HoldingRegisters,
+}
+
+impl Table {
+    /// Whether the table holds single-bit statuses rather than 16-bit registers.
+    pub fn is_bits(&self) -> bool {
+        matches!(self, Table::Coils | Table::DiscreteInputs)
+    }
+
+    /// Most addresses a single read of this table may cover.
+    pub fn max_quantity(&self) -> u16 {
+        if self.is_bits() { 2000 } else { 125 }
+    }
+
+    /// Check that `quantity` addresses from `address` can be read in one request and don't run past the last address.
+    pub fn check_range(&self, address: u16, quantity: u16) -> Result<(), String> {
+        if quantity > self.max_quantity() {
+            return Err(format!("at most {} {} can be read at once, got {}", self.max_quantity(), self, quantity));
+        }
+        if u32::from(address) + u32::from(quantity) > 0x10000 {
+            return Err(format!("{} addresses from {} run past the last address, 65535", quantity, address));
+        }
+        Ok(())
+    }
+
+    /// Format a value read from this table the same way the read command does.
+    pub fn format_value(&self, value: u16) -> String {
+        if self.is_bits() {
+            (value != 0).to_string()
+        } else {
+            format!("{:#04x}", value)
+        }
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Use the same names as on the command line.
+        match self.to_possible_value() {
+            Some(value) => write!(f, "{}", value.get_name()),
+            None => write!(f, "{:?}", self),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FifoQueue {
+    pub byte_count: u16,
+    pub fifo_count: u16,
+    pub values: Vec<u16>,
+}
+
+impl TryFrom<Vec<u8>> for FifoQueue {
+    type Error = Error;
+    fn try_from(data: Vec<u8>) -> Result<FifoQueue, Error> {
+        if data.len() < 4 {
+            return Err(Error::new(ErrorKind::InvalidData, format!("response data len {}, want >= 4", data.len())))
+        }
+
+        let byte_count = (&data[0..2]).read_u16::<BigEndian>()?;
+        let fifo_count = (&data[2..4]).read_u16::<BigEndian>()?;
+        if fifo_count > MAX_FIFO_COUNT {
+            return Err(Error::new(ErrorKind::InvalidData, format!("fifo count {}, want <= {}", fifo_count, MAX_FIFO_COUNT)))
+        }
+        // The byte count covers the FIFO count field as well as the registers.
+        if usize::from(byte_count) != 2 + 2 * usize::from(fifo_count) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("byte count {} does not match fifo count {}", byte_count, fifo_count)
+            ))
+        }
+        if data.len() != 2 + usize::from(byte_count) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("response data len {}, want {} from byte count", data.len(), 2 + usize::from(byte_count))
+            ))
+        }
+
+        let mut values: Vec<u16> = vec![];
+        for i in (4..data.len()).step_by(2) {
+            values.push((&data[i..i+2]).read_u16::<BigEndian>()?);
+        }
+
+        Ok(FifoQueue { byte_count, fifo_count, values })
+    }
+}
+
+/// Modbus Encapsulated Interface (MEI) types, carried by function 0x2B.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MeiType {
+    CanOpenGeneralReference,
+    ReadDeviceIdentification,
+    Other(u8),
+}
+
+impl From<u8> for MeiType {
+    fn from(v: u8) -> Self {
+        match v {
+            0x0D => MeiType::CanOpenGeneralReference,
+            0x0E => MeiType::ReadDeviceIdentification,
+            _ => MeiType::Other(v),
+        }
+    }
+}
+
+impl From<MeiType> for u8 {
+    fn from(v: MeiType) -> Self {
+        match v {
+            MeiType::CanOpenGeneralReference => 0x0D,
+            MeiType::ReadDeviceIdentification => 0x0E,
+            MeiType::Other(v) => v,
+        }
+    }
+}
+
+impl fmt::Display for MeiType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeiType::CanOpenGeneralReference => write!(f, "CanOpenGeneralReference"),
+            MeiType::ReadDeviceIdentification => write!(f, "ReadDeviceIdentification"),
+            MeiType::Other(v) => write!(f, "{:#04x}", v),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MeiResponse {
+    pub mei_type: MeiType,
+    pub data: Vec<u8>,
+}
+
+impl TryFrom<Vec<u8>> for MeiResponse {
+    type Error = Error;
+    fn try_from(mut data: Vec<u8>) -> Result<MeiResponse, Error> {
+        if data.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidData, "response data len 0, want >= 1"))
+        }
+        let mei_type = data.remove(0).into();
+        Ok(MeiResponse { mei_type, data })
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 #[repr(u8)] 
 pub enum DeviceIdentificationCode {
@@ -61,8 +256,8 @@ impl From<u8> for DeviceIdentificationCode {
     fn from(v: u8) -> Self {
         match v {
             1 => DeviceIdentificationCode::Basic,
-            2 => DeviceIdentificationCode::Extended,
-            3 => DeviceIdentificationCode::Regular,
+            2 => DeviceIdentificationCode::Regular,
+            3 => DeviceIdentificationCode::Extended,
             4 => DeviceIdentificationCode::Individual,
             _ => DeviceIdentificationCode::Unknown,
         }
@@ -84,16 +279,28 @@ impl From<u8> for DeviceConformity {
     fn from(v: u8) -> Self {
         match v {
             1 => DeviceConformity::Basic,
-            2 => DeviceConformity::Extended,
-            3 => DeviceConformity::Regular,
+            2 => DeviceConformity::Regular,
+            3 => DeviceConformity::Extended,
             0x81 => DeviceConformity::BasicWithIndividual,
-            0x82 => DeviceConformity::ExtendedWithIndividual,
-            0x83 => DeviceConformity::RegularWithIndividual,
+            0x82 => DeviceConformity::RegularWithIndividual,
+            0x83 => DeviceConformity::ExtendedWithIndividual,
             _ => DeviceConformity::Unknown,
         }
     }
 }
 
+impl DeviceConformity {
+    /// The highest stream access code the device claims to support.
+    pub fn stream_access(&self) -> DeviceIdentificationCode {
+        match self {
+            DeviceConformity::Basic | DeviceConformity::BasicWithIndividual => DeviceIdentificationCode::Basic,
+            DeviceConformity::Regular | DeviceConformity::RegularWithIndividual => DeviceIdentificationCode::Regular,
+            DeviceConformity::Extended | DeviceConformity::ExtendedWithIndividual => DeviceIdentificationCode::Extended,
+            DeviceConformity::Unknown => DeviceIdentificationCode::Unknown,
+        }
+    }
+}
+
 impl fmt::Display for DeviceConformity {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", match self {
@@ -115,13 +322,44 @@ pub struct DeviceIDObject {
     pub value: Vec<u8>,
 }
 
-impl From<VecDeque<u8>> for DeviceIDObject {
-    fn from(mut v: VecDeque<u8>) -> Self {
+impl DeviceIDObject {
+    /// Name of the object as defined by the Modbus specification.
+    /// Objects 0x07-0x7F are reserved, and 0x80-0xFF are vendor-specific.
+    pub fn name(&self) -> &'static str {
+        match self.id {
+            0x00 => "VendorName",
+            0x01 => "ProductCode",
+            0x02 => "MajorMinorRevision",
+            0x03 => "VendorUrl",
+            0x04 => "ProductName",
+            0x05 => "ModelName",
+            0x06 => "UserApplicationName",
+            0x07..=0x7F => "Reserved",
+            _ => "Private",
+        }
+    }
+
+    /// The object value as a string. Invalid UTF-8 sequences are replaced rather than rejected.
+    pub fn value_string(&self) -> String {
+        String::from_utf8_lossy(&self.value).to_string()
+    }
+}
+
+impl TryFrom<&mut VecDeque<u8>> for DeviceIDObject {
+    type Error = Error;
+    fn try_from(v: &mut VecDeque<u8>) -> Result<Self, Error> {
+        if v.len() < 2 {
+            return Err(Error::new(ErrorKind::InvalidData, format!("object data len {}, want >= 2", v.len())))
+        }
         let id = v.pop_front()
             .unwrap();
         let length = v.pop_front()
             .unwrap();
-        DeviceIDObject { id, length, value: v.clone().into() }
+        if v.len() < usize::from(length) {
+            return Err(Error::new(ErrorKind::InvalidData, format!("object {} len {}, only {} bytes remain", id, length, v.len())))
+        }
+        let value: Vec<u8> = v.drain(..usize::from(length)).collect();
+        Ok(DeviceIDObject { id, length, value })
     }
 }
 
@@ -136,8 +374,12 @@ pub struct DeviceIdentification {
     pub objects: Vec<DeviceIDObject>,
 }
 
-impl From<VecDeque<u8>> for DeviceIdentification {
-    fn from(mut data: VecDeque<u8>) -> DeviceIdentification {
+impl TryFrom<VecDeque<u8>> for DeviceIdentification {
+    type Error = Error;
+    fn try_from(mut data: VecDeque<u8>) -> Result<DeviceIdentification, Error> {
+        if data.len() < 6 {
+            return Err(Error::new(ErrorKind::InvalidData, format!("response data len {}, want >= 6", data.len())))
+        }
         let mei_type = data.pop_front()
             .unwrap();
         let read_device_id_code: DeviceIdentificationCode = data.pop_front()
@@ -155,14 +397,66 @@ impl From<VecDeque<u8>> for DeviceIdentification {
 
         let mut objects: Vec<DeviceIDObject> = vec![];
         for _ in 0..number_of_objects {
-            let object_len = data[1];
-            let buffer_length: usize = usize::from(2 + object_len);
-            let mut object_buffer = data.clone();
-            object_buffer.truncate(buffer_length);
-            objects.push(object_buffer.into());
+            objects.push(DeviceIDObject::try_from(&mut data)?);
+        }
+
+        Ok(DeviceIdentification{mei_type, read_device_id_code, conformity_level, more_follows, next_object_id, number_of_objects, objects})
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunIndicator {
+    Off = 0x00,
+    On = 0xFF,
+}
+
+impl fmt::Display for RunIndicator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            RunIndicator::Off => "OFF",
+            RunIndicator::On => "ON",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerIdentification {
+    pub byte_count: u8,
+    pub server_id: Vec<u8>,
+    pub run_indicator: RunIndicator,
+    pub additional_data: Vec<u8>,
+}
+
+impl ServerIdentification {
+    /// Decode a Report Server ID response. The length of the server id is device-specific and is not
+    /// encoded in the response, so it must be supplied by the caller.
+    pub fn try_from_response(data: Vec<u8>, server_id_len: usize) -> Result<ServerIdentification, Error> {
+        if data.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidData, "response data len 0, want >= 1"))
+        }
+        let byte_count = data[0];
+        if data.len() - 1 != usize::from(byte_count) {
+            return Err(Error::new(ErrorKind::InvalidData, format!("byte count {}, but {} bytes follow", byte_count, data.len() - 1)))
         }
+        if usize::from(byte_count) < server_id_len + 1 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("byte count {} too short for a {} byte server id and run indicator", byte_count, server_id_len)
+            ))
+        }
+
+        let server_id = data[1..1 + server_id_len].to_vec();
+        let run_indicator = match data[1 + server_id_len] {
+            0x00 => RunIndicator::Off,
+            0xFF => RunIndicator::On,
+            v => return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("run indicator status {:#04x}, want 0x00 or 0xff; is the server id {} bytes long?", v, server_id_len)
+            )),
+        };
+        let additional_data = data[2 + server_id_len..].to_vec();
 
-        DeviceIdentification{mei_type, read_device_id_code, conformity_level, more_follows, next_object_id, number_of_objects, objects}
+        Ok(ServerIdentification { byte_count, server_id, run_indicator, additional_data })
     }
 }
 
@@ -174,11 +468,18 @@ pub trait ReaderExt: Reader {
     async fn read_file_record(&mut self, file_number: u16, starting_record: u16, record_length: u16) -> Result<FileRecord, Error>;
     /// Read a First-In, First-Out (FIFO) queue of registers on the remote device.
     /// Up to 31 data registers can be read. Queue contents are read, but not cleared.
-    async fn read_fifo_queue(&mut self, pointer_address: u16) -> Result<Vec<u16>, Error>;
+    async fn read_fifo_queue(&mut self, pointer_address: u16) -> Result<FifoQueue, Error>;
+    /// Send a Modbus Encapsulated Interface (MEI) request of any type, returning the type-specific response data.
+    async fn encapsulated_interface_transport(&mut self, mei_type: MeiType, data: Vec<u8>) -> Result<MeiResponse, Error>;
     /// Read device identification information. Requires device to implement Modbus Encapsulated Interface (MEI).
+    /// Devices may split the objects across several responses, which are requested until `more_follows` is cleared
+    /// and merged into a single `DeviceIdentification`.
     async fn read_device_identification(&mut self, id_code: DeviceIdentificationCode, object_id: u8) -> Result<DeviceIdentification, Error>;
-    /// Read server identification. Data returned is device-specific
-    async fn read_server_identification(&mut self) -> Result<Vec<u8>, Error>;
+    /// Read server identification. The server id and additional data are device-specific,
+    /// and the server id is expected to be `server_id_len` bytes long.
+    async fn read_server_identification(&mut self, server_id_len: usize) -> Result<ServerIdentification, Error>;
+    /// Read a range from any of the four data tables. Coil and discrete input statuses are returned as 0 or 1.
+    async fn read_table(&mut self, table: Table, address: u16, quantity: u16) -> Result<Vec<u16>, Error>;
 }
 
 #[async_trait]
@@ -193,7 +494,7 @@ pub trait WriterExt: Writer {
 impl ReaderExt for Context {
     async fn read_file_record(&mut self, file_number: u16, starting_record: u16, record_length: u16) -> Result<FileRecord, Error> {
         let mut request: Vec<u8> = vec![6]; // Reference type is always 6.
-        let mut args_vec: Vec<u8> = vec![file_number, starting_record, record_length]
+        let mut args_vec: Vec<u8> = [file_number, starting_record, record_length]
             .iter()
             .flat_map(|&x| x.to_be_bytes())
             .collect();
@@ -209,53 +510,99 @@ impl ReaderExt for Context {
         
     }
 
-    async fn read_fifo_queue(&mut self, pointer_address: u16) -> Result<Vec<u16>, Error> {
+    async fn read_fifo_queue(&mut self, pointer_address: u16) -> Result<FifoQueue, Error> {
         let rsp = self.call(Request::Custom(READ_FIFO_QUEUE, pointer_address.to_be_bytes().into())).await?;
         match rsp {
             Response::Custom(_func_code, raw_vec) => {
-                let mut response_vec: Vec<u16> = vec![];
-                // The first 4 values aren't super meaningful to us, as they describe the size and number of items returned.
-                // At some point, it may be worth reading them to fail more gracefully if we recieve malformed responses,
-                // but for now we'll just rely on Vec's panic if we try to read out of bounds. 
-                for i in (4..raw_vec.len()).step_by(2) {
-                    response_vec.push((&raw_vec[i..i+1]).read_u16::<BigEndian>().unwrap());
-                }
-
-                Ok(response_vec)
+                FifoQueue::try_from(raw_vec)
             },
             _ => Err(Error::new(ErrorKind::InvalidData, "invalid response data"))
         }
     }
 
-    async fn read_device_identification(&mut self, id_code: DeviceIdentificationCode, object_id: u8) -> Result<DeviceIdentification, Error> {
-        let request: Vec<u8> = vec![MEI_CODE, id_code as u8, object_id];
-        
-        let rsp = self.call(Request::Custom(READ_DEVICE_IDENTIFICATION, request)).await?;
+    async fn encapsulated_interface_transport(&mut self, mei_type: MeiType, data: Vec<u8>) -> Result<MeiResponse, Error> {
+        let mut request: Vec<u8> = vec![mei_type.into()];
+        request.extend(data);
+        let rsp = self.call(Request::Custom(ENCAPSULATED_INTERFACE_TRANSPORT, request)).await?;
         match rsp {
-            Response::Custom(_func_code, raw_vec) => {
-                let vecdeq: VecDeque<u8> = raw_vec.into();
-                Ok(vecdeq.into())
+            Response::Custom(_func_code, response_vec) => {
+                let mei_response = MeiResponse::try_from(response_vec)?;
+                if mei_response.mei_type != mei_type {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("response MEI type {}, want {}", mei_response.mei_type, mei_type)
+                    ))
+                }
+                Ok(mei_response)
             },
             _ => Err(Error::new(ErrorKind::InvalidData, "invalid response data"))
         }
     }
 
-    async fn read_server_identification(&mut self) -> Result<Vec<u8>, Error> {
+    async fn read_device_identification(&mut self, id_code: DeviceIdentificationCode, object_id: u8) -> Result<DeviceIdentification, Error> {
+        let mut object_id = object_id;
+        let mut merged: Option<DeviceIdentification> = None;
+        loop {
+            let rsp = self.encapsulated_interface_transport(MeiType::ReadDeviceIdentification, vec![id_code as u8, object_id]).await?;
+            let mut vecdeq: VecDeque<u8> = rsp.data.into();
+            vecdeq.push_front(rsp.mei_type.into());
+            let device_id = DeviceIdentification::try_from(vecdeq)?;
+
+            // Individual access always returns a single object, so only streams can be continued.
+            let more_follows = device_id.more_follows != 0 && id_code != DeviceIdentificationCode::Individual;
+            let next_object_id = device_id.next_object_id;
+            merged = Some(match merged {
+                None => device_id,
+                Some(mut all) => {
+                    all.objects.extend(device_id.objects);
+                    all
+                },
+            });
+            if !more_follows {
+                break;
+            }
+            if next_object_id <= object_id {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("next object id {} does not advance past {}", next_object_id, object_id)
+                ))
+            }
+            object_id = next_object_id;
+        }
+
+        let mut device_id = merged.unwrap();
+        device_id.more_follows = 0;
+        device_id.next_object_id = 0;
+        device_id.number_of_objects = device_id.objects.len() as u8;
+        Ok(device_id)
+    }
+
+    async fn read_server_identification(&mut self, server_id_len: usize) -> Result<ServerIdentification, Error> {
         let rsp = self.call(Request::Custom(READ_SERVER_IDENTIFICATION, vec![])).await?;
         match rsp {
             Response::Custom(_func_code, response_vec) => {
-                Ok(response_vec)
+                ServerIdentification::try_from_response(response_vec, server_id_len)
             },
             _ => Err(Error::new(ErrorKind::InvalidData, "invalid response data"))
         }
     }
+
+    async fn read_table(&mut self, table: Table, address: u16, quantity: u16) -> Result<Vec<u16>, Error> {
+        let bits_to_words = |bits: Vec<bool>| bits.into_iter().map(u16::from).collect();
+        match table {
+            Table::Coils => self.read_coils(address, quantity).await.map(bits_to_words),
+            Table::DiscreteInputs => self.read_discrete_inputs(address, quantity).await.map(bits_to_words),
+            Table::InputRegisters => self.read_input_registers(address, quantity).await,
+            Table::HoldingRegisters => self.read_holding_registers(address, quantity).await,
+        }
+    }
 }
 
 #[async_trait]
 impl WriterExt for Context {
     async fn write_file_record(&mut self, file_number: u16, record_number: u16, record_data: Vec<u16>) -> Result<(), Error> {
         let mut request: Vec<u8> = vec![6]; // Reference type is always 6.
-        let mut args_vec: Vec<u8> = vec![file_number, record_number, record_data.len() as u16]
+        let mut args_vec: Vec<u8> = [file_number, record_number, record_data.len() as u16]
             .iter()
             .flat_map(|&x| x.to_be_bytes())
             .collect();
@@ -282,12 +629,15 @@ impl WriterExt for Context {
 }
 
 async fn get_tcp_client(host: String, port: u16) -> Result<Context, Error> {
-    let socket: SocketAddr = format!("{}:{}", host, port).parse().unwrap();
+    let socket: SocketAddr = tokio::net::lookup_host((host.as_str(), port))
+        .await?
+        .next()
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("could not resolve {}", host)))?;
     let ctx = tcp::connect(socket).await?;
     Ok(ctx)
 }
 
-async fn get_rtu_client(device_path: String, bitrate: u32, terminal_id: u8) -> Result<Context, Error> {
+pub async fn get_rtu_client(device_path: String, bitrate: u32, terminal_id: u8) -> Result<Context, Error> {
     let terminal = tokio_modbus::slave::Slave(terminal_id);
     let builder = tokio_serial::new(device_path, bitrate);
     let stream = tokio_serial::SerialStream::open(&builder)?;
@@ -295,11 +645,167 @@ async fn get_rtu_client(device_path: String, bitrate: u32, terminal_id: u8) -> R
     Ok(ctx)
 }
 
+/// A serial port that can be shared by the connections made on it one after another, and that throws away whatever is
+/// waiting to be read when a request is about to be sent. Anything there is the answer to a request that was given up
+/// on, and would otherwise be read as the answer to the new one.
+#[derive(Clone, Debug)]
+struct SharedPort {
+    inner: Arc<Mutex<SerialStream>>,
+    /// Set from the first write of a request until it is flushed, so a request written in pieces isn't cut short.
+    sending: bool,
+}
+
+impl SharedPort {
+    fn port(&self) -> MutexGuard<'_, SerialStream> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl AsyncRead for SharedPort {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut TaskContext<'_>, buf: &mut ReadBuf<'_>) -> Poll<Result<(), Error>> {
+        Pin::new(&mut *self.port()).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for SharedPort {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>, buf: &[u8]) -> Poll<Result<usize, Error>> {
+        if !self.sending {
+            self.port().clear(ClearBuffer::Input)?;
+            self.sending = true;
+        }
+        Pin::new(&mut *self.port()).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Result<(), Error>> {
+        let flushed = Pin::new(&mut *self.port()).poll_flush(cx);
+        if flushed.is_ready() {
+            self.sending = false;
+        }
+        flushed
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Result<(), Error>> {
+        Pin::new(&mut *self.port()).poll_shutdown(cx)
+    }
+}
+
+async fn open(uri: &ModbusUri, terminal_id: u8) -> Result<Context, Error> {
+    match uri.proto {
+        Proto::Tcp => {
+            let port = u16::try_from(uri.port)
+                .map_err(|_| Error::new(ErrorKind::InvalidInput, format!("invalid port {}", uri.port)))?;
+            get_tcp_client(uri.host.clone(), port).await
+        },
+        Proto::Rtu => get_rtu_client(uri.host.clone(), uri.port, terminal_id).await,
+    }
+}
+
+/// A connection for probing, which starts afresh after a request was abandoned or failed without an answer. On a serial
+/// line the port stays open and only the framing starts over, dropping any partial answer. Over TCP the connection is
+/// only opened again when asked to.
+#[derive(Debug)]
+struct Probing {
+    uri: ModbusUri,
+    terminal_id: u8,
+    port: Option<SharedPort>,
+    reconnect: bool,
+    slave: Option<Slave>,
+    inner: Context,
+    /// Set while a request is outstanding, so it stays set if the caller drops the request, e.g. on a timeout.
+    stale: bool,
+}
+
+impl SlaveContext for Probing {
+    fn set_slave(&mut self, slave: Slave) {
+        self.slave = Some(slave);
+        self.inner.set_slave(slave);
+    }
+}
+
+#[async_trait]
+impl Client for Probing {
+    async fn call(&mut self, request: Request) -> Result<Response, Error> {
+        if self.stale && request != Request::Disconnect {
+            match &self.port {
+                Some(port) => self.inner = rtu::connect_slave(port.clone(), Slave(self.terminal_id)).await?,
+                None if self.reconnect => self.inner = open(&self.uri, self.terminal_id).await?,
+                None => {},
+            }
+            if let Some(slave) = self.slave {
+                self.inner.set_slave(slave);
+            }
+        }
+        self.stale = true;
+        let result = self.inner.call(request).await;
+        // Exceptions are proper answers; anything else may have left a response in flight.
+        self.stale = matches!(&result, Err(e) if exception_code(e).is_none());
+        result
+    }
+}
+
+/// Open a connection to a single device.
+pub async fn connect(uri: ModbusUri, terminal_id: u8) -> Result<Context, Error> {
+    open(&uri, terminal_id).await
+}
+
+/// Open a connection for probing a device, where requests are expected to go unanswered. On a serial line, answers that
+/// arrive after their request was given up on are dropped before the next request. Over TCP they can't be, so with
+/// `reconnect` the connection is opened again after a request goes unanswered instead.
+pub async fn connect_probing(uri: ModbusUri, terminal_id: u8, reconnect: bool) -> Result<Context, Error> {
+    let (port, inner) = match uri.proto {
+        Proto::Tcp => (None, open(&uri, terminal_id).await?),
+        Proto::Rtu => {
+            let stream = SerialStream::open(&tokio_serial::new(uri.host.as_str(), uri.port))?;
+            let port = SharedPort { inner: Arc::new(Mutex::new(stream)), sending: false };
+            let inner = rtu::connect_slave(port.clone(), Slave(terminal_id)).await?;
+            (Some(port), inner)
+        },
+    };
+    let probing = Probing { uri, terminal_id, port, reconnect, slave: None, inner, stale: false };
+    Ok(Context::from(Box::new(probing) as Box<dyn Client>))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_range_limits_quantity_per_table() {
+        assert!(Table::Coils.check_range(0, 2000).is_ok());
+        assert!(Table::Coils.check_range(0, 2001).is_err());
+        assert!(Table::HoldingRegisters.check_range(0, 125).is_ok());
+        assert!(Table::InputRegisters.check_range(0, 126).is_err());
+    }
+
+    #[test]
+    fn check_range_stops_at_last_address() {
+        assert!(Table::HoldingRegisters.check_range(65535, 1).is_ok());
+        assert!(Table::HoldingRegisters.check_range(65535, 2).is_err());
+        assert!(Table::DiscreteInputs.check_range(64000, 1536).is_ok());
+    }
+
+    #[test]
+    fn fifo_queue_decodes_values() {
+        // Byte count 6, FIFO count 2, then 0x01B8 and 0x1284 as in the spec's example.
+        let queue = FifoQueue::try_from(vec![0x00, 0x06, 0x00, 0x02, 0x01, 0xB8, 0x12, 0x84]).unwrap();
+        assert_eq!(queue, FifoQueue { byte_count: 6, fifo_count: 2, values: vec![0x01B8, 0x1284] });
+    }
+
+    #[test]
+    fn fifo_queue_decodes_empty_queue() {
+        let queue = FifoQueue::try_from(vec![0x00, 0x02, 0x00, 0x00]).unwrap();
+        assert_eq!(queue, FifoQueue { byte_count: 2, fifo_count: 0, values: vec![] });
+    }
 
-// Can't impl TryFrom becuase this is all async
-pub async fn context_try_from(args: Args) -> Result<Context, Error> {
-    match args.uri.proto {
-        Proto::Tcp => Ok(get_tcp_client(args.uri.host, args.uri.port).await?),
-        Proto::Rtu => Ok(get_rtu_client(args.uri.host, args.uri.port.into(), args.terminal_id).await?),
+    #[test]
+    fn fifo_queue_rejects_inconsistent_counts() {
+        // Too short for the counts.
+        assert!(FifoQueue::try_from(vec![0x00, 0x02, 0x00]).is_err());
+        // More than 31 values.
+        assert!(FifoQueue::try_from(vec![0x00, 0x42, 0x00, 0x20]).is_err());
+        // Byte count that doesn't match the FIFO count.
+        assert!(FifoQueue::try_from(vec![0x00, 0x04, 0x00, 0x02, 0x01, 0xB8]).is_err());
+        // Fewer values than the byte count says.
+        assert!(FifoQueue::try_from(vec![0x00, 0x06, 0x00, 0x02, 0x01, 0xB8]).is_err());
     }
 }
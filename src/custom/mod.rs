@@ -13,9 +13,9 @@ use crate::client::ReaderExt;
 async fn get_buf_for_file(file_name: &str) -> Result<VecDeque<u8>, Error> {
     let mut buf: Vec<u8> = vec![];
     
-    if file_name == "" || file_name == "-" {
+    if file_name.is_empty() || file_name == "-" {
         stdin().lock().read_to_end(&mut buf)
-            .with_context(|| format!("failed to read stdin"))?;
+            .with_context(|| "failed to read stdin")?;
     } else {
         File::open(file_name)
             .with_context(|| format!("failed to open '{}'", file_name))?
@@ -0,0 +1,23 @@
+use clap::Args;
+
+use crate::mei;
+
+/// Decode Modbus/TCP transactions from a pcap or pcapng capture, e.g. one saved by Wireshark or tcpdump
+#[derive(Args, Clone, Debug)]
+pub struct DecodeArgs {
+    /// capture file to read
+    #[clap(value_parser)]
+    pub capture: String,
+
+    /// TCP port the server listens on
+    #[clap(long, value_parser, default_value_t = 502)]
+    pub port: u16,
+
+    /// only show transactions with this unit id. May be given several times
+    #[clap(long, value_parser = mei::parse_byte)]
+    pub unit: Vec<u8>,
+
+    /// only show transactions with this function code, e.g. 0x10. May be given several times
+    #[clap(long, value_parser = mei::parse_byte)]
+    pub function: Vec<u8>,
+}
@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::SystemTime;
+use anyhow::Error;
+
+use crate::frame::pdu::Exchange;
+use crate::frame::tcp::{self, Header};
+use crate::output::Output;
+use crate::pcap;
+
+pub mod args;
+mod net;
+
+/// Segments held back waiting for a missing one, before giving up on it as lost by the capture.
+const MAX_OUT_OF_ORDER: usize = 64;
+
+/// One direction of a TCP connection, put back in order and split into Modbus/TCP frames.
+#[derive(Default)]
+struct Stream {
+    next_sequence: Option<u32>,
+    buffer: Vec<u8>,
+    out_of_order: Vec<(u32, Vec<u8>)>,
+}
+
+impl Stream {
+    /// Append the part of a segment that hasn't been seen yet, returning whether it was in order.
+    fn append(&mut self, sequence: u32, payload: &[u8]) -> bool {
+        let next = *self.next_sequence.get_or_insert(sequence);
+        let offset = sequence.wrapping_sub(next) as i32;
+        if offset > 0 {
+            return false;
+        }
+        // Retransmissions overlap what was already appended.
+        let seen = offset.unsigned_abs() as usize;
+        if seen < payload.len() {
+            self.buffer.extend_from_slice(&payload[seen..]);
+            self.next_sequence = Some(sequence.wrapping_add(payload.len() as u32));
+        }
+        true
+    }
+
+    fn push(&mut self, sequence: u32, payload: &[u8]) {
+        if !payload.is_empty() && !self.append(sequence, payload) {
+            self.out_of_order.push((sequence, payload.to_vec()));
+        }
+        loop {
+            let before = self.out_of_order.len();
+            let held = std::mem::take(&mut self.out_of_order);
+            for (sequence, payload) in held {
+                if !self.append(sequence, &payload) {
+                    self.out_of_order.push((sequence, payload));
+                }
+            }
+            if self.out_of_order.len() == before {
+                break;
+            }
+        }
+        if self.out_of_order.len() > MAX_OUT_OF_ORDER {
+            // The capture missed a segment, so skip to the earliest one held and drop the partial frame before the gap.
+            let next = self.next_sequence.unwrap_or_default();
+            let earliest = self.out_of_order.iter().map(|(sequence, _)| *sequence).min_by_key(|sequence| sequence.wrapping_sub(next));
+            self.buffer.clear();
+            self.next_sequence = earliest;
+            self.push(earliest.unwrap_or_default(), &[]);
+        }
+    }
+
+    /// Take the next complete frame from the stream.
+    fn next_frame(&mut self) -> Option<(Header, Vec<u8>)> {
+        let header = Header::parse(self.buffer.get(..tcp::HEADER_LEN)?.try_into().ok()?);
+        if header.protocol_id != 0 || header.length < 2 || usize::from(header.length) - 1 > tcp::MAX_PDU_LEN {
+            // Captures that start mid-connection may start mid-frame too. Later segments usually start on a frame.
+            self.buffer.clear();
+            return None;
+        }
+        let len = tcp::HEADER_LEN - 1 + usize::from(header.length);
+        if self.buffer.len() < len {
+            return None;
+        }
+        let pdu = self.buffer[tcp::HEADER_LEN..len].to_vec();
+        self.buffer.drain(..len);
+        Some((header, pdu))
+    }
+}
+
+/// A request waiting for its response.
+struct Pending {
+    timestamp: SystemTime,
+    unit_id: u8,
+    pdu: Vec<u8>,
+}
+
+/// The client and server of a connection.
+type Connection = (SocketAddr, SocketAddr);
+
+struct Transactions {
+    args: args::DecodeArgs,
+    pending: HashMap<(Connection, u16), Pending>,
+    rows: Vec<(SystemTime, Vec<String>)>,
+}
+
+impl Transactions {
+    fn add(&mut self, connection: Connection, transaction_id: u16, request: Option<Pending>, response: Option<(SystemTime, u8, &[u8])>) {
+        let (timestamp, unit_id, function) = match (&request, response) {
+            (Some(request), _) => (request.timestamp, request.unit_id, request.pdu.first()),
+            (None, Some((timestamp, unit_id, pdu))) => (timestamp, unit_id, pdu.first()),
+            (None, None) => return,
+        };
+        let function = function.map(|f| f & 0x7F).unwrap_or_default();
+        if (!self.args.unit.is_empty() && !self.args.unit.contains(&unit_id))
+            || (!self.args.function.is_empty() && !self.args.function.contains(&function)) {
+            return;
+        }
+        let rtt = match (&request, response) {
+            (Some(request), Some((answered, _, _))) => answered.duration_since(request.timestamp)
+                .map(|rtt| format!("{:.1}", rtt.as_secs_f64() * 1000.0))
+                .unwrap_or_default(),
+            _ => String::new(),
+        };
+
+        let mut row = vec![
+            humantime::format_rfc3339_micros(timestamp).to_string(),
+            connection.0.to_string(),
+            connection.1.to_string(),
+            unit_id.to_string(),
+            transaction_id.to_string(),
+        ];
+        row.extend(Exchange::decode(request.as_ref().map(|r| r.pdu.as_slice()), response.map(|(_, _, pdu)| pdu)).row());
+        row.push(rtt);
+        self.rows.push((timestamp, row));
+    }
+
+    fn request(&mut self, connection: Connection, timestamp: SystemTime, header: Header, pdu: Vec<u8>) {
+        let request = Pending { timestamp, unit_id: header.unit_id, pdu };
+        // A reused transaction id means the earlier request was never answered.
+        if let Some(unanswered) = self.pending.insert((connection, header.transaction_id), request) {
+            self.add(connection, header.transaction_id, Some(unanswered), None);
+        }
+    }
+
+    fn response(&mut self, connection: Connection, timestamp: SystemTime, header: Header, pdu: Vec<u8>) {
+        let request = self.pending.remove(&(connection, header.transaction_id));
+        self.add(connection, header.transaction_id, request, Some((timestamp, header.unit_id, &pdu)));
+    }
+}
+
+/// Decode every Modbus/TCP transaction in a capture, writing them to the output in the order they were requested.
+pub fn decode_action(args: args::DecodeArgs, output: &mut dyn Output) -> Result<(), Error> {
+    let packets = pcap::read_packets(&args.capture)?;
+    let port = args.port;
+    let mut streams: HashMap<(SocketAddr, SocketAddr), Stream> = HashMap::new();
+    let mut transactions = Transactions { args, pending: HashMap::new(), rows: vec![] };
+    let mut unsupported: Vec<u32> = vec![];
+
+    for packet in packets.iter() {
+        let segment = match net::segment(packet.linktype, &packet.data) {
+            Some(segment) => segment,
+            None => {
+                if !matches!(packet.linktype, pcap::LINKTYPE_NULL | pcap::LINKTYPE_ETHERNET | pcap::LINKTYPE_RAW
+                    | pcap::LINKTYPE_LINUX_SLL | pcap::LINKTYPE_LINUX_SLL2) && !unsupported.contains(&packet.linktype) {
+                    eprintln!("skipping packets with unsupported link type {}", packet.linktype);
+                    unsupported.push(packet.linktype);
+                }
+                continue;
+            },
+        };
+        let connection = if segment.destination.port() == port {
+            (segment.source, segment.destination)
+        } else if segment.source.port() == port {
+            (segment.destination, segment.source)
+        } else {
+            continue;
+        };
+
+        let stream = streams.entry((segment.source, segment.destination)).or_default();
+        if segment.syn {
+            // A new connection starts a new stream; SYN takes up a sequence number of its own.
+            *stream = Stream { next_sequence: Some(segment.sequence.wrapping_add(1)), ..Stream::default() };
+            continue;
+        }
+        stream.push(segment.sequence, segment.payload);
+        while let Some((header, pdu)) = stream.next_frame() {
+            if segment.destination.port() == port {
+                transactions.request(connection, packet.timestamp, header, pdu);
+            } else {
+                transactions.response(connection, packet.timestamp, header, pdu);
+            }
+        }
+    }
+
+    for ((connection, transaction_id), request) in std::mem::take(&mut transactions.pending) {
+        transactions.add(connection, transaction_id, Some(request), None);
+    }
+    let mut rows = transactions.rows;
+    rows.sort_by_key(|(timestamp, _)| *timestamp);
+    eprintln!("decoded {} transactions from {} packets", rows.len(), packets.len());
+
+    let mut columns = vec![
+        "timestamp".to_string(),
+        "client".to_string(),
+        "server".to_string(),
+        "unit_id".to_string(),
+        "transaction_id".to_string(),
+    ];
+    columns.extend(Exchange::columns());
+    columns.push("rtt_ms".to_string());
+    output.write_output(columns, rows.into_iter().map(|(_, row)| row).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const READ: [u8; 5] = [0x03, 0x00, 0x64, 0x00, 0x02];
+    const WRITE: [u8; 5] = [0x06, 0x00, 0x05, 0x00, 0x07];
+
+    fn frames(stream: &mut Stream) -> Vec<(u16, Vec<u8>)> {
+        std::iter::from_fn(|| stream.next_frame())
+            .map(|(header, pdu)| (header.transaction_id, pdu))
+            .collect()
+    }
+
+    #[test]
+    fn joins_frames_split_across_segments() {
+        let frame = tcp::encode(1, 1, &READ);
+        let mut stream = Stream::default();
+        stream.push(1000, &frame[..5]);
+        assert!(frames(&mut stream).is_empty());
+        stream.push(1005, &frame[5..]);
+        assert_eq!(frames(&mut stream), [(1, READ.to_vec())]);
+    }
+
+    #[test]
+    fn splits_frames_sharing_a_segment() {
+        let segment = [tcp::encode(1, 1, &READ), tcp::encode(2, 1, &WRITE)].concat();
+        let mut stream = Stream::default();
+        stream.push(1000, &segment);
+        assert_eq!(frames(&mut stream), [(1, READ.to_vec()), (2, WRITE.to_vec())]);
+    }
+
+    #[test]
+    fn ignores_retransmissions() {
+        let first = tcp::encode(1, 1, &READ);
+        let second = tcp::encode(2, 1, &WRITE);
+        let mut stream = Stream::default();
+        stream.push(1000, &first);
+        stream.push(1000, &first);
+        // A retransmission that overlaps the end of what was seen adds only the new part.
+        stream.push(1000, &[first.clone(), second.clone()].concat());
+        assert_eq!(frames(&mut stream), [(1, READ.to_vec()), (2, WRITE.to_vec())]);
+    }
+
+    #[test]
+    fn reorders_out_of_order_segments() {
+        let first = tcp::encode(1, 1, &READ);
+        let second = tcp::encode(2, 1, &WRITE);
+        let mut stream = Stream::default();
+        stream.push(1000, &first[..4]);
+        stream.push(1000 + first.len() as u32, &second);
+        stream.push(1004, &first[4..]);
+        assert_eq!(frames(&mut stream), [(1, READ.to_vec()), (2, WRITE.to_vec())]);
+    }
+
+    #[test]
+    fn drops_data_before_a_frame_boundary() {
+        let frame = tcp::encode(1, 1, &READ);
+        let mut stream = Stream::default();
+        // The tail of a frame sent before the capture started doesn't parse as a header.
+        stream.push(1000, &[0xFF; 9]);
+        assert!(frames(&mut stream).is_empty());
+        stream.push(1009, &frame);
+        assert_eq!(frames(&mut stream), [(1, READ.to_vec())]);
+    }
+
+    #[test]
+    fn skips_segments_the_capture_missed() {
+        let mut stream = Stream::default();
+        stream.push(1000, &tcp::encode(1, 1, &READ)[..4]);
+        // A lost segment leaves every later one held back until too many are waiting.
+        let mut sequence = 2000;
+        for transaction_id in 0..=MAX_OUT_OF_ORDER as u16 {
+            let frame = tcp::encode(transaction_id, 1, &WRITE);
+            stream.push(sequence, &frame);
+            sequence += frame.len() as u32;
+        }
+        let recovered = frames(&mut stream);
+        assert_eq!(recovered.len(), MAX_OUT_OF_ORDER + 1);
+        assert_eq!(recovered[0], (0, WRITE.to_vec()));
+    }
+}
@@ -0,0 +1,108 @@
+//! Just enough of Ethernet, IP and TCP to find the TCP payloads in captured packets.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use crate::pcap;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86DD;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88A8;
+const IP_PROTOCOL_TCP: u8 = 6;
+
+const TCP_SYN: u8 = 0x02;
+
+/// A TCP segment from a captured packet.
+pub struct Segment<'a> {
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+    pub sequence: u32,
+    pub syn: bool,
+    pub payload: &'a [u8],
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*data.get(offset)?, *data.get(offset + 1)?]))
+}
+
+fn ethernet(data: &[u8]) -> Option<(u16, &[u8])> {
+    let mut ethertype = u16_at(data, 12)?;
+    let mut offset = 14;
+    while matches!(ethertype, ETHERTYPE_VLAN | ETHERTYPE_QINQ) {
+        ethertype = u16_at(data, offset + 2)?;
+        offset += 4;
+    }
+    Some((ethertype, data.get(offset..)?))
+}
+
+/// Find the network layer of a packet, as an ethertype and the bytes following the link layer header.
+fn network_layer(linktype: u32, data: &[u8]) -> Option<(u16, &[u8])> {
+    match linktype {
+        pcap::LINKTYPE_ETHERNET => ethernet(data),
+        pcap::LINKTYPE_LINUX_SLL => Some((u16_at(data, 14)?, data.get(16..)?)),
+        pcap::LINKTYPE_LINUX_SLL2 => Some((u16_at(data, 0)?, data.get(20..)?)),
+        // Loopback captures start with the address family in the capturing host's byte order.
+        pcap::LINKTYPE_NULL => {
+            let family = u32::from_le_bytes(data.get(0..4)?.try_into().ok()?);
+            let family = if family > 0xFFFF { family.swap_bytes() } else { family };
+            let ethertype = match family {
+                2 => ETHERTYPE_IPV4,
+                24 | 28 | 30 => ETHERTYPE_IPV6,
+                _ => return None,
+            };
+            Some((ethertype, data.get(4..)?))
+        },
+        pcap::LINKTYPE_RAW => match data.first()? >> 4 {
+            4 => Some((ETHERTYPE_IPV4, data)),
+            6 => Some((ETHERTYPE_IPV6, data)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Find the TCP header and payload of an IP packet, with its addresses.
+fn transport_layer(ethertype: u16, data: &[u8]) -> Option<(IpAddr, IpAddr, &[u8])> {
+    match ethertype {
+        ETHERTYPE_IPV4 => {
+            let header_len = usize::from(data.first()? & 0x0F) * 4;
+            let total_len = usize::from(u16_at(data, 2)?);
+            // Fragments are rare for Modbus' small frames, and are skipped rather than reassembled.
+            let fragment = u16_at(data, 6)?;
+            if data.get(9)? != &IP_PROTOCOL_TCP || fragment & 0x3FFF != 0 {
+                return None;
+            }
+            let source: [u8; 4] = data.get(12..16)?.try_into().ok()?;
+            let destination: [u8; 4] = data.get(16..20)?.try_into().ok()?;
+            // Ethernet pads short frames, so the IP length decides where the packet ends.
+            let end = total_len.min(data.len());
+            Some((Ipv4Addr::from(source).into(), Ipv4Addr::from(destination).into(), data.get(header_len..end)?))
+        },
+        ETHERTYPE_IPV6 => {
+            // Extension headers aren't followed.
+            if data.get(6)? != &IP_PROTOCOL_TCP {
+                return None;
+            }
+            let payload_len = usize::from(u16_at(data, 4)?);
+            let source: [u8; 16] = data.get(8..24)?.try_into().ok()?;
+            let destination: [u8; 16] = data.get(24..40)?.try_into().ok()?;
+            let end = (40 + payload_len).min(data.len());
+            Some((Ipv6Addr::from(source).into(), Ipv6Addr::from(destination).into(), data.get(40..end)?))
+        },
+        _ => None,
+    }
+}
+
+/// Parse a captured packet as a TCP segment, or None if it isn't one.
+pub fn segment(linktype: u32, data: &[u8]) -> Option<Segment<'_>> {
+    let (ethertype, data) = network_layer(linktype, data)?;
+    let (source, destination, tcp) = transport_layer(ethertype, data)?;
+    let header_len = usize::from(tcp.get(12)? >> 4) * 4;
+    Some(Segment {
+        source: SocketAddr::new(source, u16_at(tcp, 0)?),
+        destination: SocketAddr::new(destination, u16_at(tcp, 2)?),
+        sequence: u32::from_be_bytes(tcp.get(4..8)?.try_into().ok()?),
+        syn: tcp.get(13)? & TCP_SYN != 0,
+        payload: tcp.get(header_len..)?,
+    })
+}
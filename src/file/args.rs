@@ -0,0 +1,40 @@
+
+use clap::{Args, Subcommand};
+
+/// Transfer whole files to and from the remote device using file records
+#[derive(Args, Clone, Debug)]
+pub struct FileArgs {
+    #[clap(subcommand)]
+    pub function: FileFuncs,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum FileFuncs {
+    /// copy a remote file into a local file
+    Download(Transfer),
+    /// copy a local file onto the remote device
+    Upload(Transfer),
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct Transfer {
+    /// file number
+    #[clap(value_parser)]
+    pub file_number: u16,
+
+    /// local file to transfer. Each record is stored as a big-endian word
+    #[clap(value_parser)]
+    pub local_file: String,
+
+    /// record to resume the transfer from, between 0 and 9999
+    #[clap(long, value_parser = clap::value_parser!(u16).range(0..10000), default_value_t = 0)]
+    pub resume_from: u16,
+
+    /// number of records to transfer. Defaults to the rest of the file
+    #[clap(long, value_parser = clap::value_parser!(u16).range(1..10001))]
+    pub records: Option<u16>,
+
+    /// check the transfer: downloads read the local file back, uploads read the records back from the device
+    #[clap(long)]
+    pub verify: bool,
+}
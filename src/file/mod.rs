@@ -0,0 +1,195 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use anyhow::{anyhow, Context, Error};
+
+use crate::client::{self, ReaderExt, WriterExt};
+use crate::CommandResult;
+
+pub mod args;
+
+/// Each file holds records 0 through 9999.
+const FILE_RECORDS: u16 = 10000;
+/// Most records that fit in one read response, as the response data length is capped at 0xF5 bytes.
+const READ_CHUNK_RECORDS: u16 = 121;
+/// Most records that fit in one write request, as the request data length is capped at 0xFB bytes.
+const WRITE_CHUNK_RECORDS: u16 = 122;
+
+fn progress(action: &str, file_number: u16, done: u16, first: u16, end: u16) {
+    eprint!("\r{} file #{}: record {}/{}", action, file_number, done, end);
+    if done == end {
+        eprintln!(" ({} records)", end - first);
+    }
+}
+
+/// Read records `first` to `end`, handing each chunk to `save` as it arrives so that a failed read loses nothing before it.
+/// When `to_end_of_file` is set, the device refusing a record as an illegal data address is taken as the end of the file,
+/// and the records up to it are returned.
+async fn read_records<F>(client: &mut client::Context, file_number: u16, first: u16, end: u16, to_end_of_file: bool, action: &str, mut save: F) -> Result<Vec<u16>, Error>
+where
+    F: FnMut(&[u16]) -> Result<(), Error>,
+{
+    let mut records: Vec<u16> = vec![];
+    let mut record = first;
+    let mut chunk_records = READ_CHUNK_RECORDS;
+    while record < end {
+        let length = chunk_records.min(end - record);
+        let file_record = match client.read_file_record(file_number, record, length).await {
+            Ok(file_record) => file_record,
+            // The chunk runs past the end of the file, so try again with a smaller one until only the end is left.
+            Err(e) if to_end_of_file && client::exception_code(&e) == Some(client::ILLEGAL_DATA_ADDRESS) => {
+                if length == 1 {
+                    progress(action, file_number, record, first, record);
+                    break;
+                }
+                chunk_records = length / 2;
+                continue;
+            },
+            Err(e) => return Err(e)
+                .with_context(|| format!("failed to read file #{} record {}, resume with --resume-from {}", file_number, record, record)),
+        };
+        if file_record.record_data.len() != usize::from(length) {
+            return Err(anyhow!("file #{} record {}: got {} words, want {}", file_number, record, file_record.record_data.len(), length));
+        }
+        save(&file_record.record_data)?;
+        records.extend(file_record.record_data);
+        record += length;
+        progress(action, file_number, record, first, end);
+    }
+    Ok(records)
+}
+
+fn verify_records(file_number: u16, first: u16, want: &[u16], got: &[u16]) -> Result<(), Error> {
+    if got.len() != want.len() {
+        return Err(anyhow!("verification of file #{} failed: {} records read back, want {}", file_number, got.len(), want.len()));
+    }
+    let mismatches: Vec<usize> = want.iter()
+        .zip(got.iter())
+        .enumerate()
+        .filter(|(_, (w, g))| w != g)
+        .map(|(i, _)| i)
+        .collect();
+    match mismatches.first() {
+        Some(&i) => Err(anyhow!(
+            "verification of file #{} failed: {} records differ, first at record {}",
+            file_number, mismatches.len(), usize::from(first) + i
+        )),
+        None => Ok(()),
+    }
+}
+
+fn words_to_bytes(words: &[u16]) -> Vec<u8> {
+    words.iter()
+        .flat_map(|&x| x.to_be_bytes())
+        .collect()
+}
+
+fn bytes_to_words(bytes: &[u8]) -> Vec<u16> {
+    // A trailing odd byte is padded with zero to fill the last record.
+    bytes.chunks(2)
+        .map(|chunk| u16::from_be_bytes([chunk[0], *chunk.get(1).unwrap_or(&0)]))
+        .collect()
+}
+
+async fn download(client: &mut client::Context, args: args::Transfer) -> Result<CommandResult, Error> {
+    let first = args.resume_from;
+    let end = args.records.map_or(FILE_RECORDS, |n| first.saturating_add(n).min(FILE_RECORDS));
+
+    // When resuming, records already downloaded are kept and the rest is written in place.
+    let mut local_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(first == 0)
+        .open(&args.local_file)
+        .with_context(|| format!("failed to open '{}'", args.local_file))?;
+    let saved = local_file.metadata()
+        .with_context(|| format!("failed to read '{}'", args.local_file))?
+        .len() / 2;
+    if u64::from(first) > saved {
+        return Err(anyhow!("'{}' holds {} records, can't resume from record {}", args.local_file, saved, first));
+    }
+    local_file.seek(SeekFrom::Start(u64::from(first) * 2))
+        .with_context(|| format!("failed to seek in '{}'", args.local_file))?;
+
+    let local_path = args.local_file.clone();
+    // Without --records, the file is read until the device reports its end.
+    let records = read_records(client, args.file_number, first, end, args.records.is_none(), "downloading", |chunk| {
+        local_file.write_all(&words_to_bytes(chunk))
+            .and_then(|_| local_file.flush())
+            .with_context(|| format!("failed to write '{}'", local_path))
+    }).await?;
+    let end = first + records.len() as u16;
+
+    // Anything past the end is left over from an earlier, longer download.
+    local_file.set_len(u64::from(end) * 2)
+        .with_context(|| format!("failed to truncate '{}'", args.local_file))?;
+
+    if args.verify {
+        let mut buf: Vec<u8> = vec![];
+        File::open(&args.local_file)
+            .and_then(|mut f| f.read_to_end(&mut buf))
+            .with_context(|| format!("failed to read back '{}'", args.local_file))?;
+        let written = bytes_to_words(&buf[usize::from(first) * 2..]);
+        verify_records(args.file_number, first, &records, &written)?;
+    }
+
+    Ok(transfer_result(args, first, end))
+}
+
+async fn upload(client: &mut client::Context, args: args::Transfer) -> Result<CommandResult, Error> {
+    let mut buf: Vec<u8> = vec![];
+    File::open(&args.local_file)
+        .with_context(|| format!("failed to open '{}'", args.local_file))?
+        .read_to_end(&mut buf)
+        .with_context(|| format!("failed to read '{}'", args.local_file))?;
+    let words = bytes_to_words(&buf);
+    if words.len() > usize::from(FILE_RECORDS) {
+        return Err(anyhow!("'{}' holds {} records, a file can hold at most {}", args.local_file, words.len(), FILE_RECORDS));
+    }
+
+    let first = args.resume_from;
+    if usize::from(first) > words.len() {
+        return Err(anyhow!("'{}' holds {} records, can't resume from record {}", args.local_file, words.len(), first));
+    }
+    let available = (words.len() as u16).saturating_sub(first);
+    let end = first + args.records.map_or(available, |n| n.min(available));
+    let records = &words[usize::from(first)..usize::from(end)];
+
+    let mut record = first;
+    for chunk in records.chunks(usize::from(WRITE_CHUNK_RECORDS)) {
+        client.write_file_record(args.file_number, record, chunk.to_vec())
+            .await
+            .with_context(|| format!("failed to write file #{} record {}, resume with --resume-from {}", args.file_number, record, record))?;
+        record += chunk.len() as u16;
+        progress("uploading", args.file_number, record, first, end);
+    }
+
+    if args.verify {
+        let read_back = read_records(client, args.file_number, first, end, false, "verifying", |_| Ok(())).await?;
+        verify_records(args.file_number, first, records, &read_back)?;
+    }
+
+    Ok(transfer_result(args, first, end))
+}
+
+fn transfer_result(args: args::Transfer, first: u16, end: u16) -> CommandResult {
+    let columns = vec![
+        "file_number".to_string(),
+        "first_record".to_string(),
+        "records".to_string(),
+        "verified".to_string(),
+    ];
+    let rows = vec![vec![
+        args.file_number.to_string(),
+        first.to_string(),
+        (end - first).to_string(),
+        args.verify.to_string(),
+    ]];
+    CommandResult { columns, rows }
+}
+
+pub async fn file_action(client: &mut client::Context, args: args::FileArgs) -> Result<CommandResult, Error> {
+    match args.function {
+        args::FileFuncs::Download(transfer) => download(client, transfer).await,
+        args::FileFuncs::Upload(transfer) => upload(client, transfer).await,
+    }
+}
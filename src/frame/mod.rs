@@ -0,0 +1,5 @@
+//! Modbus framing, for the places where mbc talks to the wire itself rather than through tokio-modbus.
+
+pub mod pdu;
+pub mod rtu;
+pub mod tcp;
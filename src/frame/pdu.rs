@@ -0,0 +1,152 @@
+//! Conversions between PDUs and tokio-modbus requests and responses.
+
+use std::io::Error;
+use bytes::Bytes;
+use tokio_modbus::prelude::{Request, Response};
+
+pub const GATEWAY_PATH_UNAVAILABLE: u8 = 0x0A;
+pub const GATEWAY_TARGET_FAILED: u8 = 0x0B;
+
+pub fn decode_request(pdu: &[u8]) -> Result<Request, Error> {
+    Request::try_from(Bytes::copy_from_slice(pdu))
+}
+
+pub fn encode_request(request: Request) -> Vec<u8> {
+    Bytes::from(request).to_vec()
+}
+
+pub fn encode_response(response: Response) -> Vec<u8> {
+    Bytes::from(response).to_vec()
+}
+
+/// An exception response to a request for `function`.
+pub fn exception(function: u8, code: u8) -> Vec<u8> {
+    vec![function | 0x80, code]
+}
+
+fn function_name(function: u8) -> &'static str {
+    match function & 0x7F {
+        0x01 => "read coils",
+        0x02 => "read discrete inputs",
+        0x03 => "read holding registers",
+        0x04 => "read input registers",
+        0x05 => "write single coil",
+        0x06 => "write single register",
+        0x07 => "read exception status",
+        0x08 => "diagnostics",
+        0x0B => "get comm event counter",
+        0x0C => "get comm event log",
+        0x0F => "write multiple coils",
+        0x10 => "write multiple registers",
+        0x11 => "report server id",
+        0x14 => "read file record",
+        0x15 => "write file record",
+        0x16 => "mask write register",
+        0x17 => "read/write multiple registers",
+        0x18 => "read fifo queue",
+        0x2B => "encapsulated interface transport",
+        _ => "unknown",
+    }
+}
+
+/// The big-endian word at `offset`, if the PDU is long enough to hold it.
+pub fn word(pdu: &[u8], offset: usize) -> Option<u16> {
+    pdu.get(offset..offset + 2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+}
+
+fn bits(bytes: &[u8], count: usize) -> String {
+    (0..count.min(bytes.len() * 8))
+        .map(|i| (bytes[i / 8] & (1 << (i % 8)) != 0).to_string())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn registers(bytes: &[u8]) -> String {
+    bytes.chunks_exact(2)
+        .map(|b| format!("{:#04x}", u16::from_be_bytes([b[0], b[1]])))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter()
+        .map(|x| format!("{:02X}", x))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// A request and its response, decoded for display. Either side may be missing, e.g. when a request went unanswered.
+#[derive(Clone, Debug, Default)]
+pub struct Exchange {
+    pub function: String,
+    pub address: String,
+    pub quantity: String,
+    pub values: String,
+    pub exception: String,
+}
+
+impl Exchange {
+    pub fn columns() -> Vec<String> {
+        vec![
+            "function".to_string(),
+            "address".to_string(),
+            "quantity".to_string(),
+            "values".to_string(),
+            "exception".to_string(),
+        ]
+    }
+
+    pub fn row(self) -> Vec<String> {
+        vec![self.function, self.address, self.quantity, self.values, self.exception]
+    }
+
+    /// Decode what was asked and answered. Values come from the response for reads and from the request for writes;
+    /// anything not understood is shown as hex.
+    pub fn decode(request: Option<&[u8]>, response: Option<&[u8]>) -> Exchange {
+        let function = match request.or(response).and_then(|pdu| pdu.first()) {
+            Some(&function) => function & 0x7F,
+            None => return Exchange::default(),
+        };
+        let mut exchange = Exchange {
+            function: format!("{:#04x} {}", function, function_name(function)),
+            ..Exchange::default()
+        };
+
+        let request = request.unwrap_or_default();
+        let (address, quantity) = match function {
+            0x01..=0x04 | 0x0F | 0x10 | 0x17 => (word(request, 1), word(request, 3)),
+            0x05 | 0x06 | 0x16 => (word(request, 1), Some(1)),
+            0x18 => (word(request, 1), None),
+            _ => (None, None),
+        };
+        exchange.address = address.map(|a| a.to_string()).unwrap_or_default();
+        exchange.quantity = quantity.map(|q| q.to_string()).unwrap_or_default();
+
+        match response {
+            Some(response) if response.first().is_some_and(|&f| f & 0x80 != 0) => {
+                exchange.exception = match response.get(1) {
+                    Some(&code) => crate::client::exception_name(code),
+                    None => "truncated exception".to_string(),
+                };
+                return exchange;
+            },
+            None if request.is_empty() => return exchange,
+            None => exchange.exception = "no response".to_string(),
+            Some(_) => {},
+        }
+
+        let response = response.unwrap_or_default();
+        let quantity = usize::from(quantity.unwrap_or_default());
+        exchange.values = match function {
+            0x01 | 0x02 => response.get(2..).map(|b| bits(b, quantity)).unwrap_or_default(),
+            0x03 | 0x04 | 0x17 => response.get(2..).map(registers).unwrap_or_default(),
+            0x05 => word(request, 3).map(|v| (v == 0xFF00).to_string()).unwrap_or_default(),
+            0x06 => word(request, 3).map(|v| format!("{:#04x}", v)).unwrap_or_default(),
+            0x0F => request.get(6..).map(|b| bits(b, quantity)).unwrap_or_default(),
+            0x10 => request.get(6..).map(registers).unwrap_or_default(),
+            0x18 => response.get(5..).map(registers).unwrap_or_default(),
+            _ => hex(response.get(1..).unwrap_or_default()),
+        };
+        exchange
+    }
+}
@@ -0,0 +1,154 @@
+//! RTU framing: a unit id, the PDU, and a CRC-16 sent low byte first.
+
+use std::io::Error;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Smallest valid frame: unit id, function code and CRC.
+pub const MIN_FRAME_LEN: usize = 4;
+/// Largest valid frame: unit id, 253 byte PDU and CRC.
+pub const MAX_FRAME_LEN: usize = 256;
+
+const CRC_INIT: u16 = 0xFFFF;
+
+fn crc_update(mut crc: u16, byte: u8) -> u16 {
+    crc ^= u16::from(byte);
+    for _ in 0..8 {
+        crc = if crc & 1 != 0 { (crc >> 1) ^ 0xA001 } else { crc >> 1 };
+    }
+    crc
+}
+
+/// CRC-16/MODBUS of `data`.
+pub fn crc(data: &[u8]) -> u16 {
+    data.iter().fold(CRC_INIT, |crc, &byte| crc_update(crc, byte))
+}
+
+/// Build a frame addressed to `unit_id`.
+pub fn encode(unit_id: u8, pdu: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(pdu.len() + 3);
+    frame.push(unit_id);
+    frame.extend_from_slice(pdu);
+    frame.extend(crc(&frame).to_le_bytes());
+    frame
+}
+
+/// Split a frame into its unit id and PDU, or None if it is too short or the CRC doesn't match.
+pub fn decode(frame: &[u8]) -> Option<(u8, &[u8])> {
+    if frame.len() < MIN_FRAME_LEN || frame.len() > MAX_FRAME_LEN {
+        return None;
+    }
+    let (body, checksum) = frame.split_at(frame.len() - 2);
+    if crc(body).to_le_bytes() != checksum {
+        return None;
+    }
+    Some((body[0], &body[1..]))
+}
+
+/// Length of the shortest frame with a valid CRC at the start of `data`, if there is one.
+pub fn frame_len(data: &[u8]) -> Option<usize> {
+    let mut crc = CRC_INIT;
+    for len in 1..=data.len().min(MAX_FRAME_LEN) {
+        // The CRC covers everything but its own two bytes, so keep it two bytes behind.
+        if len > 2 {
+            crc = crc_update(crc, data[len - 3]);
+        }
+        if len >= MIN_FRAME_LEN && crc.to_le_bytes() == data[len - 2..len] {
+            return Some(len);
+        }
+    }
+    None
+}
+
+/// The silent interval that separates frames: 3.5 character times of 11 bits each.
+/// Above 19200 baud the spec fixes it at 1.75ms.
+pub fn frame_gap(baud_rate: u32) -> Duration {
+    if baud_rate > 19200 {
+        Duration::from_micros(1750)
+    } else {
+        Duration::from_micros(38_500_000 / u64::from(baud_rate.max(1)))
+    }
+}
+
+/// Read one frame: wait up to `timeout` for it to start (forever if None), then read until the line has been quiet for `gap`.
+/// Returns an empty frame if nothing arrived in time or the stream ended.
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R, timeout: Option<Duration>, gap: Duration) -> Result<Vec<u8>, Error> {
+    read_until_quiet(reader, timeout, gap, MAX_FRAME_LEN).await
+}
+
+/// Like `read_frame`, but keeps reading past the largest frame until the line goes quiet, for frames run together.
+pub async fn read_burst<R: AsyncRead + Unpin>(reader: &mut R, timeout: Option<Duration>, gap: Duration) -> Result<Vec<u8>, Error> {
+    read_until_quiet(reader, timeout, gap, usize::MAX).await
+}
+
+async fn read_until_quiet<R: AsyncRead + Unpin>(reader: &mut R, timeout: Option<Duration>, gap: Duration, limit: usize) -> Result<Vec<u8>, Error> {
+    let mut frame: Vec<u8> = vec![];
+    let mut buf = [0u8; MAX_FRAME_LEN];
+    while frame.len() < limit {
+        let read = reader.read(&mut buf);
+        let wait = if frame.is_empty() { timeout } else { Some(gap) };
+        let n = match wait {
+            Some(wait) => match tokio::time::timeout(wait, read).await {
+                Ok(n) => n?,
+                Err(_) => break,
+            },
+            None => read.await?,
+        };
+        if n == 0 {
+            break;
+        }
+        frame.extend_from_slice(&buf[..n]);
+    }
+    Ok(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc_matches_known_frame() {
+        assert_eq!(crc(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x0A]).to_le_bytes(), [0xC5, 0xCD]);
+    }
+
+    #[test]
+    fn encode_appends_crc_low_byte_first() {
+        assert_eq!(encode(0x01, &[0x03, 0x00, 0x00, 0x00, 0x0A]), [0x01, 0x03, 0x00, 0x00, 0x00, 0x0A, 0xC5, 0xCD]);
+    }
+
+    #[test]
+    fn decode_splits_valid_frame() {
+        let frame = [0x01, 0x03, 0x00, 0x00, 0x00, 0x0A, 0xC5, 0xCD];
+        assert_eq!(decode(&frame), Some((0x01, &frame[1..6])));
+    }
+
+    #[test]
+    fn decode_rejects_bad_crc_and_short_frames() {
+        assert_eq!(decode(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x0A, 0xCD, 0xC5]), None);
+        assert_eq!(decode(&[0x01, 0x03, 0x00]), None);
+        assert_eq!(decode(&[0u8; MAX_FRAME_LEN + 1]), None);
+    }
+
+    #[test]
+    fn frame_len_finds_shortest_valid_prefix() {
+        let frames = [encode(1, &[0x03, 0x00, 0x00, 0x00, 0x0A]), encode(1, &[0x03, 0x02, 0x00, 0x07])].concat();
+        assert_eq!(frame_len(&frames), Some(8));
+        assert_eq!(frame_len(&frames[8..]), Some(7));
+        assert_eq!(frame_len(&frames[1..]), None);
+        assert_eq!(frame_len(&[]), None);
+    }
+
+    #[test]
+    fn frame_gap_is_fixed_above_19200_baud() {
+        assert_eq!(frame_gap(9600), Duration::from_micros(4010));
+        assert_eq!(frame_gap(115200), Duration::from_micros(1750));
+    }
+
+    #[tokio::test]
+    async fn read_frame_stops_at_end_of_stream() {
+        let mut reader: &[u8] = &[0x01, 0x03, 0x00, 0x00, 0x00, 0x0A, 0xC5, 0xCD];
+        let frame = read_frame(&mut reader, Some(Duration::from_millis(100)), Duration::from_millis(10)).await.unwrap();
+        assert_eq!(frame, [0x01, 0x03, 0x00, 0x00, 0x00, 0x0A, 0xC5, 0xCD]);
+        assert!(read_frame(&mut reader, Some(Duration::from_millis(100)), Duration::from_millis(10)).await.unwrap().is_empty());
+    }
+}
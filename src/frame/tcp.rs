@@ -0,0 +1,57 @@
+//! Modbus/TCP framing: a 7 byte MBAP header followed by the PDU.
+
+use std::io::{Error, ErrorKind};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Length of the MBAP header, including the unit id.
+pub const HEADER_LEN: usize = 7;
+/// Largest PDU a frame may carry.
+pub const MAX_PDU_LEN: usize = 253;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub transaction_id: u16,
+    pub protocol_id: u16,
+    /// Bytes following the length field: the unit id and the PDU
+    pub length: u16,
+    pub unit_id: u8,
+}
+
+impl Header {
+    pub fn parse(header: &[u8; HEADER_LEN]) -> Header {
+        Header {
+            transaction_id: u16::from_be_bytes([header[0], header[1]]),
+            protocol_id: u16::from_be_bytes([header[2], header[3]]),
+            length: u16::from_be_bytes([header[4], header[5]]),
+            unit_id: header[6],
+        }
+    }
+}
+
+/// Build a frame carrying `pdu`.
+pub fn encode(transaction_id: u16, unit_id: u8, pdu: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(HEADER_LEN + pdu.len());
+    frame.extend(transaction_id.to_be_bytes());
+    frame.extend(0u16.to_be_bytes());
+    frame.extend((pdu.len() as u16 + 1).to_be_bytes());
+    frame.push(unit_id);
+    frame.extend_from_slice(pdu);
+    frame
+}
+
+/// Read the next frame from a stream, or None if the stream ended cleanly between frames.
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<(Header, Vec<u8>)>, Error> {
+    let mut header = [0u8; HEADER_LEN];
+    match reader.read_exact(&mut header).await {
+        Ok(_) => {},
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let header = Header::parse(&header);
+    if header.protocol_id != 0 || header.length < 2 || usize::from(header.length) - 1 > MAX_PDU_LEN {
+        return Err(Error::new(ErrorKind::InvalidData, format!("invalid MBAP header {:?}", header)));
+    }
+    let mut pdu = vec![0u8; usize::from(header.length) - 1];
+    reader.read_exact(&mut pdu).await?;
+    Ok(Some((header, pdu)))
+}
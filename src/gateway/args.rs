@@ -0,0 +1,15 @@
+
+use std::time::Duration;
+use clap::Args;
+
+/// Forward Modbus/TCP requests onto the RTU line given by the URI
+#[derive(Args, Clone, Debug)]
+pub struct GatewayArgs {
+    /// address to accept Modbus/TCP connections on
+    #[clap(long, value_parser, default_value = "0.0.0.0:502")]
+    pub listen: String,
+
+    /// how long to wait for a device on the serial line to answer
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "1s")]
+    pub timeout: Duration,
+}
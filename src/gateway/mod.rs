@@ -0,0 +1,116 @@
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::{anyhow, Context, Error};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use tokio_modbus::prelude::{Client, Slave, SlaveContext};
+
+use crate::client;
+use crate::frame::{pdu, rtu, tcp};
+use crate::uri::{ModbusUri, Proto};
+
+pub mod args;
+
+/// How long to leave the line quiet after a broadcast, for the devices to carry it out.
+const BROADCAST_TURNAROUND: Duration = Duration::from_millis(100);
+
+/// The serial line, opened on first use and reopened after a failure, so a response that arrives late
+/// can't be mistaken for the answer to the next request.
+struct Line {
+    uri: ModbusUri,
+    client: Option<client::Context>,
+}
+
+impl Line {
+    /// Forward a request to a unit, answering with a gateway exception if the line or the unit fails.
+    /// Broadcasts are forwarded but never answered, so they return None.
+    async fn forward(&mut self, unit_id: u8, pdu: &[u8], args: &args::GatewayArgs) -> Option<Vec<u8>> {
+        let function = pdu.first().copied().unwrap_or_default();
+        // Ids reserved by the spec have no device to answer them.
+        if unit_id > 247 {
+            return Some(pdu::exception(function, pdu::GATEWAY_PATH_UNAVAILABLE));
+        }
+        let request = match pdu::decode_request(pdu) {
+            Ok(request) => request,
+            Err(_) if pdu.is_empty() => return Some(pdu::exception(function, client::ILLEGAL_FUNCTION)),
+            Err(_) => return Some(pdu::exception(function, client::ILLEGAL_DATA_VALUE)),
+        };
+
+        if self.client.is_none() {
+            match client::get_rtu_client(self.uri.host.clone(), self.uri.port, unit_id).await {
+                Ok(client) => self.client = Some(client),
+                Err(e) => {
+                    eprintln!("could not open `{}`: {}", self.uri, e);
+                    return (unit_id != 0).then(|| pdu::exception(function, pdu::GATEWAY_PATH_UNAVAILABLE));
+                },
+            }
+        }
+        let client = self.client.as_mut().unwrap();
+        client.set_slave(Slave(unit_id));
+
+        if unit_id == 0 {
+            // The client waits for an answer that never comes, so it is given long enough to send the frame and then
+            // dropped, to be reopened for the next request.
+            let bits_per_frame = 11 * (rtu::encode(unit_id, pdu).len() as u64);
+            let sending = Duration::from_micros(bits_per_frame * 1_000_000 / u64::from(self.uri.port.max(1)));
+            let _ = tokio::time::timeout(sending + BROADCAST_TURNAROUND, client.call(request)).await;
+            self.client = None;
+            return None;
+        }
+
+        let response = match tokio::time::timeout(args.timeout, client.call(request)).await {
+            Ok(Ok(response)) => pdu::encode_response(response),
+            Ok(Err(e)) => match client::exception_code(&e) {
+                Some(code) => pdu::exception(function, code),
+                None => {
+                    eprintln!("unit {}: {}", unit_id, e);
+                    self.client = None;
+                    pdu::exception(function, pdu::GATEWAY_TARGET_FAILED)
+                },
+            },
+            Err(_) => {
+                self.client = None;
+                pdu::exception(function, pdu::GATEWAY_TARGET_FAILED)
+            },
+        };
+        Some(response)
+    }
+}
+
+async fn forward_connection(mut stream: TcpStream, line: Arc<Mutex<Line>>, args: Arc<args::GatewayArgs>) -> Result<(), Error> {
+    while let Some((header, request)) = tcp::read_frame(&mut stream).await? {
+        // The line is held for the whole exchange, as only one request can be in flight on a serial bus.
+        let response = line.lock().await.forward(header.unit_id, &request, &args).await;
+        if let Some(response) = response {
+            stream.write_all(&tcp::encode(header.transaction_id, header.unit_id, &response)).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Accept Modbus/TCP connections and forward their requests onto an RTU line, one at a time, until interrupted.
+pub async fn gateway_action(uri: ModbusUri, args: args::GatewayArgs) -> Result<(), Error> {
+    if !matches!(uri.proto, Proto::Rtu) {
+        return Err(anyhow!("a gateway forwards onto an rtu:// line, got `{}`", uri));
+    }
+    let listener = TcpListener::bind(args.listen.as_str())
+        .await
+        .with_context(|| format!("could not listen on `{}`", args.listen))?;
+    eprintln!("forwarding {} to {}", listener.local_addr()?, uri);
+
+    let line = Arc::new(Mutex::new(Line { uri, client: None }));
+    let args = Arc::new(args);
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        eprintln!("{} connected", peer);
+        let line = line.clone();
+        let args = args.clone();
+        tokio::spawn(async move {
+            match forward_connection(stream, line, args).await {
+                Ok(()) => eprintln!("{} disconnected", peer),
+                Err(e) => eprintln!("{} disconnected: {:#}", peer, e),
+            }
+        });
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::BTreeMap;
+use anyhow::{Context, Error};
+
+use crate::client::{DeviceConformity, DeviceIdentificationCode, DeviceIDObject, ReaderExt};
+use crate::CommandResult;
+
+/// First object of each identification stream.
+const STREAMS: [(DeviceIdentificationCode, u8); 3] = [
+    (DeviceIdentificationCode::Basic, 0x00),
+    (DeviceIdentificationCode::Regular, 0x03),
+    (DeviceIdentificationCode::Extended, 0x80),
+];
+
+pub async fn identify_action(client: &mut dyn ReaderExt) -> Result<CommandResult, Error> {
+    let mut objects: BTreeMap<u8, DeviceIDObject> = BTreeMap::new();
+    let mut conformity_level: Option<DeviceConformity> = None;
+
+    for (id_code, object_id) in STREAMS {
+        // The conformity level reported with the basic objects tells us which other streams are worth asking for.
+        let supported = conformity_level
+            .as_ref()
+            .map_or(DeviceIdentificationCode::Basic, |level| level.stream_access());
+        if id_code > supported {
+            break;
+        }
+
+        let device_id = client.read_device_identification(id_code, object_id)
+            .await
+            .with_context(|| format!("failed to read {:?} device identification", id_code))?;
+        for object in device_id.objects {
+            objects.insert(object.id, object);
+        }
+        conformity_level.get_or_insert(device_id.conformity_level);
+    }
+
+    let conformity_level = conformity_level.map(|level| level.to_string()).unwrap_or_default();
+    let rows: Vec<Vec<String>> = objects
+        .values()
+        .map(|o| vec![
+            o.id.to_string(),
+            o.name().to_string(),
+            o.value_string(),
+            conformity_level.clone(),
+        ])
+        .collect();
+    let columns = vec![
+        "object_id".to_string(),
+        "name".to_string(),
+        "value".to_string(),
+        "conformity_level".to_string(),
+    ];
+    Ok(CommandResult { columns, rows })
+}
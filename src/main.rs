@@ -1,14 +1,27 @@
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use clap::{Parser};
-use output::OutputPlugin;
-use std::{io::{stdout, Write}, fs::OpenOptions};
-use tokio;
+use std::{io::{stdout, Write}, fs::OpenOptions, process};
 
+mod alarm;
 mod args;
 mod client;
 mod custom;
+mod decode;
+mod file;
+mod frame;
+mod gateway;
+mod identify;
+mod mei;
+mod monitor;
 mod read;
+mod scan;
+mod serve;
+mod session;
+mod sniff;
 mod output;
+mod pcap;
+mod proxy;
+mod watch;
 mod write;
 mod uri;
 
@@ -21,6 +34,22 @@ pub struct CommandResult {
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = args::Args::parse();
+    // When checking alarms, failures have to be reported as a status too, so monitoring doesn't mistake them for a WARNING.
+    let checking_alarms = matches!(&args.action, args::Action::Read(read_args) if !read_args.alarms().is_empty());
+
+    match run(args).await {
+        Ok(alarm::Status::Ok) => Ok(()),
+        Ok(status) => process::exit(status as i32),
+        Err(e) if checking_alarms => {
+            eprintln!("{} - {:#}", alarm::Status::Critical, e);
+            process::exit(alarm::Status::Critical as i32)
+        },
+        Err(e) => Err(e),
+    }
+}
+
+/// Run the requested action, returning the alarm status to exit with.
+async fn run(args: args::Args) -> Result<alarm::Status> {
     let file: Box<dyn Write> = match args.clone().output_file.as_str() {
         "stdout" => Box::new(stdout()),
         _ => {
@@ -29,33 +58,164 @@ async fn main() -> Result<()> {
                 .append(true)
                 .create(true)
                 .open(file_name)
-                .with_context(|| format!("could not open output file"))?
+                .with_context(|| "could not open output file")?
             )
         }
     };
 
-    let mut client = client::context_try_from(args.clone())
-        .await
-        .with_context(|| format!("could not open `{}`", args.uri))?;
+    let mut outputter = output::new_output(args.output_plugin, file);
+
+    let capturing = args.record.is_some() || args.pcap.is_some();
+    if capturing && !args.action.connects() {
+        return Err(anyhow!("--record and --pcap only capture actions that connect to a device as a client"));
+    }
+
+    // Network and serial scans find their own devices rather than connecting to a given one.
+    if let args::Action::Scan(scan_args) = &args.action {
+        if !scan_args.function.needs_connection() {
+            scan::standalone_scan_action(scan_args.clone(), args.parallelism, outputter.as_mut()).await?;
+            return Ok(alarm::Status::Ok);
+        }
+    }
+
+    // Captures are decoded offline.
+    if let args::Action::Decode(decode_args) = &args.action {
+        decode::decode_action(decode_args.clone(), outputter.as_mut())
+            .with_context(|| format!("failed to decode `{}`", decode_args.capture))?;
+        return Ok(alarm::Status::Ok);
+    }
+
+    let targets = args.targets()?;
+    if targets.len() > 1 {
+        if capturing {
+            return Err(anyhow!("--record and --pcap can't be used with several targets"));
+        }
+        return match args.action {
+            args::Action::Read(read_args) if !read_args.is_polling() && read_args.alarms().is_empty() => {
+                read::read_many_action(targets, args.terminal_id, read_args, args.parallelism, args.device_timeout, outputter.as_mut())
+                    .await?;
+                Ok(alarm::Status::Ok)
+            },
+            _ => Err(anyhow!("only single reads can be run against several targets")),
+        };
+    }
+
+    let uri = targets.into_iter().next().ok_or_else(|| anyhow!("a URI or --target is required"))?;
+    match args.action {
+        args::Action::Serve(serve_args) => {
+            serve::serve_action(uri, serve_args)
+                .await
+                .with_context(|| "failed to serve")?;
+            return Ok(alarm::Status::Ok);
+        },
+        args::Action::Gateway(gateway_args) => {
+            gateway::gateway_action(uri, gateway_args)
+                .await
+                .with_context(|| "failed to run gateway")?;
+            return Ok(alarm::Status::Ok);
+        },
+        args::Action::Replay(replay_args) if replay_args.serve => {
+            session::serve_recording(uri, replay_args)
+                .await
+                .with_context(|| "failed to serve recording")?;
+            return Ok(alarm::Status::Ok);
+        },
+        args::Action::Proxy(proxy_args) => {
+            proxy::proxy_action(uri, proxy_args, outputter.as_mut())
+                .await
+                .with_context(|| "failed to run proxy")?;
+            return Ok(alarm::Status::Ok);
+        },
+        args::Action::Sniff(sniff_args) => {
+            sniff::sniff_action(uri, sniff_args, outputter.as_mut())
+                .await
+                .with_context(|| "failed to sniff")?;
+            return Ok(alarm::Status::Ok);
+        },
+        _ => {},
+    }
 
+    // Scans expect probes to go unanswered, so their connection copes with answers that arrive too late.
+    let connected = match &args.action {
+        args::Action::Scan(scan_args) => client::connect_probing(uri.clone(), args.terminal_id, scan_args.function.reconnects()).await,
+        _ => client::connect(uri.clone(), args.terminal_id).await,
+    };
+    let mut client = connected.with_context(|| format!("could not open `{}`", uri))?;
+    // TCP connections address unit 255 until told otherwise.
+    let unit_id = match uri.proto {
+        uri::Proto::Tcp => 0xFF,
+        uri::Proto::Rtu => args.terminal_id,
+    };
+    if let Some(path) = &args.pcap {
+        client = pcap::capture(client, &uri, unit_id, path)?;
+    }
+    if let Some(path) = &args.record {
+        client = session::record(client, unit_id, path)?;
+    }
+
+    let mut status = alarm::Status::Ok;
     let result = match args.action {
-        args::Action::Read(read_args) => read::read_action(&mut client, read_args.clone())
-            .await
-            .with_context(|| format!("could not read `{:?}`", read_args))?,
+        args::Action::Read(read_args) if read_args.is_polling() => {
+            return read::poll_action(&mut client, read_args.clone(), outputter.as_mut())
+                .await
+                .with_context(|| format!("could not poll `{:?}`", read_args));
+        },
+        args::Action::Watch(watch_args) => {
+            watch::watch_action(&mut client, watch_args, outputter.as_mut())
+                .await
+                .with_context(|| "failed to watch")?;
+            return Ok(alarm::Status::Ok);
+        },
+        args::Action::Monitor(monitor_args) => {
+            monitor::monitor_action(&mut client, monitor_args)
+                .await
+                .with_context(|| "failed to monitor")?;
+            return Ok(alarm::Status::Ok);
+        },
+        args::Action::Serve(_) | args::Action::Gateway(_) | args::Action::Proxy(_) | args::Action::Decode(_)
+        | args::Action::Sniff(_) => unreachable!("handled before connecting"),
+        args::Action::Replay(replay_args) => {
+            session::replay_action(&mut client, replay_args, outputter.as_mut())
+                .await
+                .with_context(|| "failed to replay session")?;
+            return Ok(alarm::Status::Ok);
+        },
+        args::Action::Scan(scan_args) => {
+            scan::scan_action(&mut client, scan_args, outputter.as_mut()).await?;
+            return Ok(alarm::Status::Ok);
+        },
+        args::Action::Read(read_args) => {
+            let result = read::read_action(&mut client, read_args.clone())
+                .await
+                .with_context(|| format!("could not read `{:?}`", read_args))?;
+            let alarms = read_args.alarms();
+            if !alarms.is_empty() {
+                let read = read_args.function.table();
+                status = alarms.check(read, &result);
+                if status != alarm::Status::Ok {
+                    alarms.run_hook(status, read, &result);
+                }
+            }
+            result
+        },
         args::Action::Custom(custom_args) => custom::custom_action(&mut client, custom_args)
             .await
             .with_context(|| "failed to send custom command")?,
         args::Action::Write(write_args) => write::write_action(&mut client, write_args.clone())
             .await
             .with_context(|| "failed to write")?,
+        args::Action::File(file_args) => file::file_action(&mut client, file_args)
+            .await
+            .with_context(|| "failed to transfer file")?,
+        args::Action::Identify => identify::identify_action(&mut client)
+            .await
+            .with_context(|| "failed to identify device")?,
+        args::Action::Mei(mei_args) => mei::mei_action(&mut client, mei_args)
+            .await
+            .with_context(|| "failed to send MEI request")?,
     };
 
-    let mut outputter: Box<dyn output::Output> = match args.output_plugin {
-        OutputPlugin::Csv => Box::new(output::CsvOutput{file}),
-        OutputPlugin::Tsv => Box::new(output::TsvOutput{file}),
-        OutputPlugin::Json => Box::new(output::JsonOutput{file}),
-    };
     outputter.write_output(result.columns, result.rows)
         .with_context(|| format!("failed to write output using {:?}", args.output_plugin))?;
-    Ok(())
+    Ok(status)
 }
@@ -0,0 +1,88 @@
+use std::collections::VecDeque;
+use anyhow::{Context, Error};
+use clap::Args;
+
+use crate::client::{DeviceIdentification, MeiResponse, MeiType, ReaderExt};
+use crate::CommandResult;
+
+pub fn parse_byte(s: &str) -> Result<u8, String> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => s.parse::<u8>(),
+    }.map_err(|e| format!("invalid byte '{}': {}", s, e))
+}
+
+fn parse_hex(s: &str) -> Result<Vec<u8>, String> {
+    let hex = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(s);
+    if !hex.len().is_multiple_of(2) {
+        return Err(format!("invalid payload '{}': odd number of hex digits", s));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i+2], 16).map_err(|e| format!("invalid payload '{}': {}", s, e)))
+        .collect()
+}
+
+/// Send a Modbus Encapsulated Interface (MEI) request, such as CANopen General Reference (0x0D)
+#[derive(Args, Clone, Debug)]
+pub struct MeiArgs {
+    /// MEI type, e.g. 0x0D for CANopen General Reference or 0x0E for Read Device Identification
+    #[clap(value_parser = parse_byte)]
+    pub mei_type: u8,
+
+    /// request payload as hex bytes, e.g. 0102ff. Multiple values are concatenated
+    #[clap(value_parser = parse_hex)]
+    pub payload: Vec<Vec<u8>>,
+}
+
+fn hex_rows(data: &[u8]) -> Vec<Vec<String>> {
+    data.chunks(16)
+        .enumerate()
+        .map(|(i, chunk)| vec![
+            format!("{:#04}:", i * 16),
+            chunk.iter()
+                .map(|x| format!("{:02X}", x))
+                .collect::<Vec<String>>()
+                .join(" "),
+            chunk.iter()
+                .map(|&x| if x.is_ascii_graphic() || x == b' ' { x as char } else { '.' })
+                .collect(),
+        ])
+        .collect()
+}
+
+fn decode_response(response: MeiResponse) -> Result<CommandResult, Error> {
+    match response.mei_type {
+        MeiType::ReadDeviceIdentification => {
+            let mut data: VecDeque<u8> = response.data.into();
+            data.push_front(response.mei_type.into());
+            let device_id = DeviceIdentification::try_from(data)
+                .with_context(|| "failed to decode device identification response")?;
+            let rows: Vec<Vec<String>> = device_id.objects
+                .iter()
+                .map(|o| vec![
+                    o.id.to_string(),
+                    o.name().to_string(),
+                    o.value_string(),
+                ])
+                .collect();
+            let columns = vec!["object_id".to_string(), "name".to_string(), "value".to_string()];
+            Ok(CommandResult { columns, rows })
+        },
+        // CANopen General Reference and other MEI types carry data whose layout is defined outside of Modbus,
+        // so these are shown as an xxd-style dump.
+        _ => {
+            let columns = vec!["offset".to_string(), "value".to_string(), "ascii".to_string()];
+            Ok(CommandResult { columns, rows: hex_rows(&response.data) })
+        },
+    }
+}
+
+pub async fn mei_action(client: &mut dyn ReaderExt, args: MeiArgs) -> Result<CommandResult, Error> {
+    let mei_type = MeiType::from(args.mei_type);
+    let payload: Vec<u8> = args.payload.concat();
+    let response = client.encapsulated_interface_transport(mei_type, payload)
+        .await
+        .with_context(|| format!("failed to send MEI type {} request", mei_type))?;
+    decode_response(response)
+}
@@ -0,0 +1,24 @@
+
+use std::time::Duration;
+use clap::Args;
+use crate::client::Table;
+
+/// Show a live, full-screen table of a range, with in-place editing of coils and holding registers
+#[derive(Args, Clone, Debug)]
+pub struct MonitorArgs {
+    /// table to monitor
+    #[clap(value_enum)]
+    pub table: Table,
+
+    /// starting address
+    #[clap(value_parser)]
+    pub address: u16,
+
+    /// number of addresses to monitor, up to 2000 coils or inputs, or 125 registers
+    #[clap(value_parser = clap::value_parser!(u16).range(1..2001))]
+    pub quantity: u16,
+
+    /// time between polls, e.g. 500ms or 1m
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "1s")]
+    pub interval: Duration,
+}
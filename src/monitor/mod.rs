@@ -0,0 +1,240 @@
+use std::io::{stdout, Stdout, Write};
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Error};
+use crossterm::{cursor, execute, queue, terminal};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Color, Print, SetAttribute, SetForegroundColor};
+use tokio_modbus::prelude::Writer;
+
+use crate::client::{self, ReaderExt, Table};
+
+pub mod args;
+
+/// Longest we wait for a key press before checking whether the next poll is due.
+const INPUT_POLL: Duration = Duration::from_millis(50);
+/// Lines used by the title, column names, and the help/status footer.
+const CHROME_LINES: u16 = 4;
+
+struct Monitor {
+    args: args::MonitorArgs,
+    values: Vec<Option<u16>>,
+    changed: Vec<bool>,
+    selected: usize,
+    scroll: usize,
+    polls: u64,
+    errors: u64,
+    latency: Option<Duration>,
+    status: String,
+    /// Text typed so far while editing the selected register.
+    edit: Option<String>,
+}
+
+fn parse_value(s: &str) -> Result<u16, Error> {
+    let s = s.trim();
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse::<u16>(),
+    }.map_err(|e| anyhow!("invalid value '{}': {}", s, e))
+}
+
+impl Monitor {
+    fn new(args: args::MonitorArgs) -> Monitor {
+        let quantity = usize::from(args.quantity);
+        Monitor {
+            args,
+            values: vec![None; quantity],
+            changed: vec![false; quantity],
+            selected: 0,
+            scroll: 0,
+            polls: 0,
+            errors: 0,
+            latency: None,
+            status: String::new(),
+            edit: None,
+        }
+    }
+
+    fn selected_address(&self) -> u16 {
+        self.args.address + self.selected as u16
+    }
+
+    async fn poll(&mut self, client: &mut client::Context) {
+        let started = Instant::now();
+        let result = client.read_table(self.args.table, self.args.address, self.args.quantity).await;
+        self.polls += 1;
+        match result {
+            Ok(values) => {
+                self.latency = Some(started.elapsed());
+                for (i, value) in values.into_iter().enumerate() {
+                    self.changed[i] = self.values[i].is_some_and(|last| last != value);
+                    self.values[i] = Some(value);
+                }
+            },
+            Err(e) => {
+                self.errors += 1;
+                self.status = format!("poll failed: {}", e);
+            },
+        }
+    }
+
+    async fn write(&mut self, client: &mut client::Context, value: u16) {
+        let address = self.selected_address();
+        let result = match self.args.table {
+            Table::Coils => client.write_single_coil(address, value != 0).await,
+            Table::HoldingRegisters => client.write_single_register(address, value).await,
+            _ => return,
+        };
+        self.status = match result {
+            Ok(()) => format!("wrote {} to {}", self.args.table.format_value(value), address),
+            Err(e) => {
+                self.errors += 1;
+                format!("write to {} failed: {}", address, e)
+            },
+        };
+    }
+
+    /// Handle a key press, returning false once the user asks to quit.
+    async fn handle_key(&mut self, client: &mut client::Context, key: KeyEvent) -> bool {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return false;
+        }
+
+        if let Some(edit) = self.edit.as_mut() {
+            match key.code {
+                KeyCode::Char(c) if c.is_ascii_hexdigit() || c == 'x' || c == 'X' => edit.push(c),
+                KeyCode::Backspace => { edit.pop(); },
+                KeyCode::Esc => self.edit = None,
+                KeyCode::Enter => {
+                    let text = self.edit.take().unwrap_or_default();
+                    match parse_value(&text) {
+                        Ok(value) => self.write(client, value).await,
+                        Err(e) => self.status = e.to_string(),
+                    }
+                },
+                _ => {},
+            }
+            return true;
+        }
+
+        let last = self.values.len() - 1;
+        let page = usize::from(terminal::size().map_or(10, |(_, rows)| rows.saturating_sub(CHROME_LINES)).max(1));
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => self.selected = (self.selected + 1).min(last),
+            KeyCode::PageUp => self.selected = self.selected.saturating_sub(page),
+            KeyCode::PageDown => self.selected = (self.selected + page).min(last),
+            KeyCode::Home => self.selected = 0,
+            KeyCode::End => self.selected = last,
+            KeyCode::Enter | KeyCode::Char(' ') => match self.args.table {
+                Table::Coils => {
+                    let value = u16::from(self.values[self.selected] == Some(0));
+                    self.write(client, value).await;
+                },
+                Table::HoldingRegisters => self.edit = Some(String::new()),
+                _ => self.status = format!("{} are read-only", self.args.table),
+            },
+            _ => {},
+        }
+        true
+    }
+
+    fn draw(&mut self, out: &mut Stdout) -> Result<(), Error> {
+        let (width, height) = terminal::size()?;
+        let visible = usize::from(height.saturating_sub(CHROME_LINES)).max(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + visible {
+            self.scroll = self.selected + 1 - visible;
+        }
+
+        queue!(out, terminal::Clear(terminal::ClearType::All), cursor::MoveTo(0, 0))?;
+        let latency = self.latency.map_or("-".to_string(), |l| format!("{:.1}ms", l.as_secs_f64() * 1000.0));
+        let title = format!(
+            "{} {}-{} every {}  polls: {}  errors: {}  latency: {}",
+            self.args.table,
+            self.args.address,
+            usize::from(self.args.address) + self.values.len() - 1,
+            humantime::format_duration(self.args.interval),
+            self.polls,
+            self.errors,
+            latency,
+        );
+        queue!(out, SetAttribute(Attribute::Bold), Print(truncate(&title, width)), SetAttribute(Attribute::Reset))?;
+        queue!(out, cursor::MoveTo(0, 1), SetAttribute(Attribute::Underlined), Print(format!("{:<10}{:<10}{:<10}", "address", "value", "hex")), SetAttribute(Attribute::Reset))?;
+
+        for (line, i) in (self.scroll..self.values.len().min(self.scroll + visible)).enumerate() {
+            let address = usize::from(self.args.address) + i;
+            let (value, hex) = match self.values[i] {
+                Some(v) if self.args.table.is_bits() => ((v != 0).to_string(), String::new()),
+                Some(v) => (v.to_string(), format!("{:#06x}", v)),
+                None => ("-".to_string(), String::new()),
+            };
+            queue!(out, cursor::MoveTo(0, 2 + line as u16))?;
+            if i == self.selected {
+                queue!(out, SetAttribute(Attribute::Reverse))?;
+            }
+            if self.changed[i] {
+                queue!(out, SetForegroundColor(Color::Yellow), SetAttribute(Attribute::Bold))?;
+            }
+            queue!(out, Print(format!("{:<10}{:<10}{:<10}", address, value, hex)), SetAttribute(Attribute::Reset))?;
+        }
+
+        let footer = match &self.edit {
+            Some(edit) => format!("new value for {} (Enter to write, Esc to cancel): {}", self.selected_address(), edit),
+            None => format!("q: quit  arrows: move  enter: edit/toggle  {}", self.status),
+        };
+        queue!(out, cursor::MoveTo(0, height.saturating_sub(1)), Print(truncate(&footer, width)))?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+fn truncate(s: &str, width: u16) -> String {
+    s.chars().take(usize::from(width)).collect()
+}
+
+async fn run(client: &mut client::Context, monitor: &mut Monitor, out: &mut Stdout) -> Result<(), Error> {
+    let mut next_poll = Instant::now();
+    loop {
+        if Instant::now() >= next_poll {
+            monitor.poll(client).await;
+            monitor.draw(out)?;
+            // Schedule from the previous deadline so polls don't drift, unless we've fallen behind entirely.
+            next_poll += monitor.args.interval;
+            if next_poll < Instant::now() {
+                next_poll = Instant::now() + monitor.args.interval;
+            }
+        }
+
+        let timeout = next_poll.saturating_duration_since(Instant::now()).min(INPUT_POLL);
+        if !tokio::task::block_in_place(|| event::poll(timeout))? {
+            continue;
+        }
+        match tokio::task::block_in_place(event::read)? {
+            Event::Key(key) if key.kind != KeyEventKind::Release => {
+                if !monitor.handle_key(client, key).await {
+                    return Ok(());
+                }
+                monitor.draw(out)?;
+            },
+            Event::Resize(_, _) => monitor.draw(out)?,
+            _ => {},
+        }
+    }
+}
+
+pub async fn monitor_action(client: &mut client::Context, args: args::MonitorArgs) -> Result<(), Error> {
+    // Checked up front, as addresses are worked out from the selected row.
+    args.table.check_range(args.address, args.quantity).map_err(|e| anyhow!(e))?;
+    let mut monitor = Monitor::new(args);
+    let mut out = stdout();
+
+    terminal::enable_raw_mode()?;
+    execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+    let result = run(client, &mut monitor, &mut out).await;
+    // Always give the terminal back, even if the monitor failed.
+    execute!(out, cursor::Show, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}
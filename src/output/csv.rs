@@ -8,8 +8,12 @@ pub struct CsvOutput {
 }
 
 impl Output for CsvOutput {
-    fn write_output(&mut self, columns: Vec<String>, rows: Vec<Vec<String>>) -> Result<(), Error> {
+    fn write_header(&mut self, columns: &[String]) -> Result<(), Error> {
         writeln!(self.file, "{}", columns.join(","))?;
+        Ok(())
+    }
+
+    fn write_rows(&mut self, rows: &[Vec<String>]) -> Result<(), Error> {
         for row in rows.iter() {
             write!(
                 self.file,
@@ -19,9 +23,10 @@ impl Output for CsvOutput {
                     .collect::<Vec<String>>()
                     .join(",")
             )?;
-            write!(self.file, "\n")?;
+            writeln!(self.file)?;
         }
+        self.file.flush()?;
         
         Ok(())
     }
-}
\ No newline at end of file
+}
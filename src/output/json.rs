@@ -5,15 +5,22 @@ use crate::output::Output;
 
 pub struct JsonOutput {
     pub file: Box<dyn Write>,
+    /// Column names from the header, used as the keys of each row object.
+    pub columns: Vec<String>,
 }
 
 impl Output for JsonOutput {
-    fn write_output(&mut self, columns: Vec<String>, rows: Vec<Vec<String>>) -> Result<(), Error> {
+    fn write_header(&mut self, columns: &[String]) -> Result<(), Error> {
+        self.columns = columns.to_vec();
+        Ok(())
+    }
+
+    fn write_rows(&mut self, rows: &[Vec<String>]) -> Result<(), Error> {
         for row in rows.iter() {
             let mut row_map: HashMap<String, String> = HashMap::new();
             for (idx, value) in row.iter().enumerate() {
                 row_map.insert(
-                    columns.get(idx).unwrap().to_string(), 
+                    self.columns.get(idx).unwrap().to_string(), 
                     value.to_string()
                 );
             }
@@ -24,9 +31,10 @@ impl Output for JsonOutput {
                 "{}",
                 json_blob
             )?;
-            write!(self.file, "\n")?;
+            writeln!(self.file)?;
         }
+        self.file.flush()?;
         
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -1,3 +1,4 @@
+use std::io::Write;
 use anyhow::Error;
 use clap::ValueEnum;
 
@@ -10,7 +11,15 @@ pub use crate::output::tsv::TsvOutput;
 
 
 pub trait Output {
-    fn write_output(&mut self, columns: Vec<String>, rows: Vec<Vec<String>>) -> Result<(), Error>;
+    /// Write the column names. This is called once, before any rows are written.
+    fn write_header(&mut self, columns: &[String]) -> Result<(), Error>;
+    /// Write a batch of rows. Rows are flushed as they are written, so results can be streamed.
+    fn write_rows(&mut self, rows: &[Vec<String>]) -> Result<(), Error>;
+
+    fn write_output(&mut self, columns: Vec<String>, rows: Vec<Vec<String>>) -> Result<(), Error> {
+        self.write_header(&columns)?;
+        self.write_rows(&rows)
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -18,4 +27,12 @@ pub enum OutputPlugin {
     Csv,
     Tsv,
     Json,
-}
\ No newline at end of file
+}
+
+pub fn new_output(plugin: OutputPlugin, file: Box<dyn Write>) -> Box<dyn Output> {
+    match plugin {
+        OutputPlugin::Csv => Box::new(CsvOutput{file}),
+        OutputPlugin::Tsv => Box::new(TsvOutput{file}),
+        OutputPlugin::Json => Box::new(JsonOutput{file, columns: vec![]}),
+    }
+}
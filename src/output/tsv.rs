@@ -8,13 +8,18 @@ pub struct TsvOutput {
 }
 
 impl Output for TsvOutput {
-    fn write_output(&mut self, columns: Vec<String>, rows: Vec<Vec<String>>) -> Result<(), Error> {
+    fn write_header(&mut self, columns: &[String]) -> Result<(), Error> {
         writeln!(self.file, "{}", columns.join("\t"))?;
+        Ok(())
+    }
+
+    fn write_rows(&mut self, rows: &[Vec<String>]) -> Result<(), Error> {
         for row in rows.iter() {
             write!(self.file, "{}", row.join("\t"))?;
-            write!(self.file, "\n")?;
+            writeln!(self.file)?;
         }
+        self.file.flush()?;
         
         Ok(())
     }
-}
\ No newline at end of file
+}
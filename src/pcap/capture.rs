@@ -0,0 +1,211 @@
+use std::fmt;
+use std::fs::File;
+use std::io::Error;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::SystemTime;
+use anyhow::Context as _;
+use async_trait::async_trait;
+use tokio_modbus::prelude::{Client, Request, Response, Slave, SlaveContext};
+
+use crate::client::{self, Context};
+use crate::frame::{pdu, rtu, tcp};
+use crate::uri::{ModbusUri, Proto};
+use super::{Writer, LINKTYPE_RAW, LINKTYPE_USER0};
+
+/// Port the client side of TCP captures uses, as tokio-modbus doesn't expose the socket to read the real one from.
+const CLIENT_PORT: u16 = 49152;
+const IP_PROTOCOL_TCP: u8 = 6;
+const TCP_PSH_ACK: u8 = 0x18;
+
+/// One's complement sum of 16-bit words, as used by the IPv4 and TCP checksums.
+fn checksum(parts: &[&[u8]]) -> u16 {
+    let mut sum: u32 = 0;
+    for part in parts {
+        for word in part.chunks(2) {
+            sum += u32::from(u16::from_be_bytes([word[0], word.get(1).copied().unwrap_or_default()]));
+        }
+    }
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Build an IP packet carrying a TCP segment of `payload`.
+fn ip_packet(source: SocketAddr, destination: SocketAddr, sequence: u32, acknowledgement: u32, payload: &[u8]) -> Vec<u8> {
+    let mut segment = Vec::with_capacity(20 + payload.len());
+    segment.extend(source.port().to_be_bytes());
+    segment.extend(destination.port().to_be_bytes());
+    segment.extend(sequence.to_be_bytes());
+    segment.extend(acknowledgement.to_be_bytes());
+    segment.extend([5 << 4, TCP_PSH_ACK]);
+    segment.extend(u16::MAX.to_be_bytes());
+    segment.extend([0u8; 4]);
+    segment.extend_from_slice(payload);
+    let tcp_len = (segment.len() as u32).to_be_bytes();
+
+    let (mut packet, pseudo_header) = match (source.ip(), destination.ip()) {
+        (IpAddr::V4(source), IpAddr::V4(destination)) => {
+            let mut header = vec![0x45, 0];
+            header.extend((20 + segment.len() as u16).to_be_bytes());
+            // Identification, then don't fragment.
+            header.extend([0, 0, 0x40, 0, 64, IP_PROTOCOL_TCP, 0, 0]);
+            header.extend(source.octets());
+            header.extend(destination.octets());
+            let header_checksum = checksum(&[&header]);
+            header[10..12].copy_from_slice(&header_checksum.to_be_bytes());
+            let mut pseudo_header = [source.octets(), destination.octets()].concat();
+            pseudo_header.extend([0, IP_PROTOCOL_TCP]);
+            pseudo_header.extend(&tcp_len[2..]);
+            (header, pseudo_header)
+        },
+        (source, destination) => {
+            let source = to_ipv6(source);
+            let destination = to_ipv6(destination);
+            let mut header = vec![0x60, 0, 0, 0];
+            header.extend((segment.len() as u16).to_be_bytes());
+            header.extend([IP_PROTOCOL_TCP, 64]);
+            header.extend(source.octets());
+            header.extend(destination.octets());
+            let mut pseudo_header = [source.octets(), destination.octets()].concat();
+            pseudo_header.extend(tcp_len);
+            pseudo_header.extend([0, 0, 0, IP_PROTOCOL_TCP]);
+            (header, pseudo_header)
+        },
+    };
+    let segment_checksum = checksum(&[&pseudo_header, &segment]);
+    segment[16..18].copy_from_slice(&segment_checksum.to_be_bytes());
+    packet.extend(segment);
+    packet
+}
+
+fn to_ipv6(address: IpAddr) -> Ipv6Addr {
+    match address {
+        IpAddr::V4(address) => address.to_ipv6_mapped(),
+        IpAddr::V6(address) => address,
+    }
+}
+
+/// How frames are put on the wire, and what's needed to keep synthesized TCP headers consistent.
+enum Framing {
+    Tcp {
+        client: SocketAddr,
+        server: SocketAddr,
+        client_sequence: u32,
+        server_sequence: u32,
+        transaction_id: u16,
+    },
+    Rtu,
+}
+
+/// A client that writes every frame it sends and receives to a pcap file.
+struct Capturer {
+    inner: Box<dyn Client>,
+    unit_id: u8,
+    framing: Framing,
+    writer: Writer,
+}
+
+impl fmt::Debug for Capturer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Capturer").field("inner", &self.inner).field("unit_id", &self.unit_id).finish()
+    }
+}
+
+impl SlaveContext for Capturer {
+    fn set_slave(&mut self, slave: Slave) {
+        self.unit_id = slave.0;
+        self.inner.set_slave(slave);
+    }
+}
+
+impl Capturer {
+    /// Frame a PDU the way it went over the wire, from the client if `request` and from the server otherwise.
+    fn packet(&mut self, pdu: &[u8], request: bool) -> Vec<u8> {
+        match &mut self.framing {
+            Framing::Tcp { client, server, client_sequence, server_sequence, transaction_id } => {
+                let frame = tcp::encode(*transaction_id, self.unit_id, pdu);
+                let len = frame.len() as u32;
+                if request {
+                    let packet = ip_packet(*client, *server, *client_sequence, *server_sequence, &frame);
+                    *client_sequence = client_sequence.wrapping_add(len);
+                    packet
+                } else {
+                    let packet = ip_packet(*server, *client, *server_sequence, *client_sequence, &frame);
+                    *server_sequence = server_sequence.wrapping_add(len);
+                    packet
+                }
+            },
+            Framing::Rtu => rtu::encode(self.unit_id, pdu),
+        }
+    }
+}
+
+#[async_trait]
+impl Client for Capturer {
+    async fn call(&mut self, request: Request) -> Result<Response, Error> {
+        if request == Request::Disconnect {
+            return self.inner.call(request).await;
+        }
+        let request_pdu = pdu::encode_request(request.clone());
+        let sent = SystemTime::now();
+        let result = self.inner.call(request).await;
+        let received = SystemTime::now();
+
+        let packet = self.packet(&request_pdu, true);
+        self.writer.write_packet(sent, &packet)?;
+        // Exceptions come back as errors, so their frames are rebuilt from the code. Other errors, like timeouts, had no response.
+        let response_pdu = match &result {
+            Ok(response) => Some(pdu::encode_response(response.clone())),
+            Err(e) => client::exception_code(e).map(|code| pdu::exception(request_pdu[0], code)),
+        };
+        if let Some(response_pdu) = response_pdu {
+            let packet = self.packet(&response_pdu, false);
+            self.writer.write_packet(received, &packet)?;
+        }
+        if let Framing::Tcp { transaction_id, .. } = &mut self.framing {
+            *transaction_id = transaction_id.wrapping_add(1);
+        }
+        result
+    }
+}
+
+/// The local address the system sends from to reach `server`, which is the one the client connection is bound to.
+/// Connecting a UDP socket picks the route without sending anything.
+fn local_ip(server: SocketAddr) -> Result<IpAddr, Error> {
+    let unspecified: IpAddr = match server {
+        SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+    };
+    let socket = UdpSocket::bind((unspecified, 0))?;
+    socket.connect(server)?;
+    Ok(socket.local_addr()?.ip())
+}
+
+/// Wrap a connection so every frame sent through it is written to a new pcap file at `path`.
+/// TCP frames get IP and TCP headers from the local address of the connection, with a fixed client port.
+pub fn capture(context: Context, uri: &ModbusUri, unit_id: u8, path: &str) -> Result<Context, anyhow::Error> {
+    let (framing, linktype) = match uri.proto {
+        Proto::Tcp => {
+            let server = (uri.host.as_str(), u16::try_from(uri.port)?)
+                .to_socket_addrs()
+                .with_context(|| format!("could not resolve `{}`", uri.host))?
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("could not resolve `{}`", uri.host))?;
+            let client_ip = local_ip(server).with_context(|| format!("could not find the local address used to reach `{}`", server))?;
+            let framing = Framing::Tcp {
+                client: SocketAddr::new(client_ip, CLIENT_PORT),
+                server,
+                client_sequence: 1,
+                server_sequence: 1,
+                transaction_id: 0,
+            };
+            (framing, LINKTYPE_RAW)
+        },
+        Proto::Rtu => (Framing::Rtu, LINKTYPE_USER0),
+    };
+    let file = File::create(path).with_context(|| format!("could not create capture file '{}'", path))?;
+    let writer = Writer::new(file, linktype).with_context(|| format!("could not write to '{}'", path))?;
+    let capturer = Capturer { inner: context.into(), unit_id, framing, writer };
+    Ok(Context::from(Box::new(capturer) as Box<dyn Client>))
+}
@@ -0,0 +1,257 @@
+//! Reading pcap and pcapng capture files, and capturing mbc's own traffic to pcap.
+
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::{anyhow, Context, Error};
+
+mod capture;
+mod writer;
+
+pub use capture::capture;
+pub use writer::Writer;
+
+pub const LINKTYPE_NULL: u32 = 0;
+pub const LINKTYPE_ETHERNET: u32 = 1;
+pub const LINKTYPE_RAW: u32 = 101;
+pub const LINKTYPE_LINUX_SLL: u32 = 113;
+pub const LINKTYPE_LINUX_SLL2: u32 = 276;
+/// Reserved for private use. Wireshark decodes it as Modbus RTU once mbrtu is set for User 0 in its DLT_USER preferences.
+pub const LINKTYPE_USER0: u32 = 147;
+
+const PCAP_MAGIC_MICROS: u32 = 0xA1B2C3D4;
+const PCAP_MAGIC_NANOS: u32 = 0xA1B23C4D;
+const PCAPNG_SECTION_HEADER: u32 = 0x0A0D0D0A;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1A2B3C4D;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 0x00000001;
+const PCAPNG_PACKET: u32 = 0x00000002;
+const PCAPNG_SIMPLE_PACKET: u32 = 0x00000003;
+const PCAPNG_ENHANCED_PACKET: u32 = 0x00000006;
+/// Interface option giving the timestamp resolution.
+const IF_TSRESOL: u16 = 9;
+
+/// A captured packet and the link layer it was captured on.
+pub struct Packet {
+    pub timestamp: SystemTime,
+    pub linktype: u32,
+    pub data: Vec<u8>,
+}
+
+fn u16_at(data: &[u8], offset: usize, big_endian: bool) -> u16 {
+    let bytes = [data[offset], data[offset + 1]];
+    if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) }
+}
+
+fn u32_at(data: &[u8], offset: usize, big_endian: bool) -> u32 {
+    let bytes = [data[offset], data[offset + 1], data[offset + 2], data[offset + 3]];
+    if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) }
+}
+
+fn truncated(what: &str) -> Error {
+    anyhow!("capture is truncated in {}", what)
+}
+
+fn read_pcap(data: &[u8]) -> Result<Vec<Packet>, Error> {
+    if data.len() < 24 {
+        return Err(truncated("the file header"));
+    }
+    let (big_endian, nanos) = match (u32::from_le_bytes(data[0..4].try_into()?), u32::from_be_bytes(data[0..4].try_into()?)) {
+        (PCAP_MAGIC_MICROS, _) => (false, false),
+        (PCAP_MAGIC_NANOS, _) => (false, true),
+        (_, PCAP_MAGIC_MICROS) => (true, false),
+        (_, PCAP_MAGIC_NANOS) => (true, true),
+        _ => return Err(anyhow!("not a pcap file")),
+    };
+    let linktype = u32_at(data, 20, big_endian) & 0x0FFF_FFFF;
+
+    let mut packets = vec![];
+    let mut offset = 24;
+    while offset < data.len() {
+        let header = data.get(offset..offset + 16).ok_or_else(|| truncated("a packet header"))?;
+        let seconds = u32_at(header, 0, big_endian);
+        let fraction = u32_at(header, 4, big_endian);
+        let captured = u32_at(header, 8, big_endian) as usize;
+        let packet = data.get(offset + 16..offset + 16 + captured).ok_or_else(|| truncated("a packet"))?;
+        let fraction = if nanos { Duration::from_nanos(fraction.into()) } else { Duration::from_micros(fraction.into()) };
+        packets.push(Packet {
+            timestamp: UNIX_EPOCH + Duration::from_secs(seconds.into()) + fraction,
+            linktype,
+            data: packet.to_vec(),
+        });
+        offset += 16 + captured;
+    }
+    Ok(packets)
+}
+
+/// A pcapng interface: its link type and how many timestamp units make a second.
+struct Interface {
+    linktype: u32,
+    units_per_second: u64,
+}
+
+fn interface_resolution(options: &[u8], big_endian: bool) -> u64 {
+    let mut offset = 0;
+    while offset + 4 <= options.len() {
+        let code = u16_at(options, offset, big_endian);
+        let length = usize::from(u16_at(options, offset + 2, big_endian));
+        if code == 0 {
+            break;
+        }
+        if code == IF_TSRESOL && length >= 1 && offset + 4 < options.len() {
+            let resolution = options[offset + 4];
+            let exponent = u32::from(resolution & 0x7F);
+            // The top bit selects a power of two rather than a power of ten.
+            let base: u64 = if resolution & 0x80 != 0 { 2 } else { 10 };
+            return base.checked_pow(exponent).unwrap_or(1_000_000);
+        }
+        offset += 4 + length.div_ceil(4) * 4;
+    }
+    1_000_000
+}
+
+fn read_pcapng(data: &[u8]) -> Result<Vec<Packet>, Error> {
+    let mut packets = vec![];
+    let mut interfaces: Vec<Interface> = vec![];
+    let mut big_endian = false;
+    let mut offset = 0;
+    while offset + 12 <= data.len() {
+        let block_type = u32_at(data, offset, big_endian);
+        if block_type == PCAPNG_SECTION_HEADER {
+            // Each section sets its own byte order and starts over with interfaces.
+            big_endian = u32::from_le_bytes(data[offset + 8..offset + 12].try_into()?) != PCAPNG_BYTE_ORDER_MAGIC;
+            interfaces.clear();
+        }
+        let length = u32_at(data, offset + 4, big_endian) as usize;
+        if length < 12 || !length.is_multiple_of(4) {
+            return Err(anyhow!("invalid pcapng block length {} at offset {}", length, offset));
+        }
+        let block = data.get(offset..offset + length).ok_or_else(|| truncated("a block"))?;
+        let body = &block[8..length - 4];
+
+        match block_type {
+            PCAPNG_INTERFACE_DESCRIPTION if body.len() >= 8 => interfaces.push(Interface {
+                linktype: u32::from(u16_at(body, 0, big_endian)),
+                units_per_second: interface_resolution(&body[8..], big_endian),
+            }),
+            PCAPNG_ENHANCED_PACKET | PCAPNG_PACKET if body.len() >= 20 => {
+                // The obsolete packet block has a 16-bit interface id followed by a drops count.
+                let interface = if block_type == PCAPNG_PACKET {
+                    usize::from(u16_at(body, 0, big_endian))
+                } else {
+                    u32_at(body, 0, big_endian) as usize
+                };
+                let interface = interfaces.get(interface).ok_or_else(|| anyhow!("packet refers to unknown interface {}", interface))?;
+                let timestamp = (u64::from(u32_at(body, 4, big_endian)) << 32) | u64::from(u32_at(body, 8, big_endian));
+                let captured = u32_at(body, 12, big_endian) as usize;
+                let packet = body.get(20..20 + captured).ok_or_else(|| truncated("a packet"))?;
+                let seconds = timestamp / interface.units_per_second;
+                let remainder = timestamp % interface.units_per_second;
+                let nanos = remainder as u128 * 1_000_000_000 / u128::from(interface.units_per_second);
+                packets.push(Packet {
+                    timestamp: UNIX_EPOCH + Duration::from_secs(seconds) + Duration::from_nanos(nanos as u64),
+                    linktype: interface.linktype,
+                    data: packet.to_vec(),
+                });
+            },
+            PCAPNG_SIMPLE_PACKET if body.len() >= 4 => {
+                let interface = interfaces.first().ok_or_else(|| anyhow!("packet refers to unknown interface 0"))?;
+                let original = u32_at(body, 0, big_endian) as usize;
+                let packet = &body[4..body.len().min(4 + original)];
+                // Simple packets carry no timestamp.
+                packets.push(Packet { timestamp: UNIX_EPOCH, linktype: interface.linktype, data: packet.to_vec() });
+            },
+            _ => {},
+        }
+        offset += length;
+    }
+    Ok(packets)
+}
+
+/// Read every packet of a pcap or pcapng file.
+pub fn read_packets(path: &str) -> Result<Vec<Packet>, Error> {
+    let mut data = vec![];
+    BufReader::new(File::open(path).with_context(|| format!("failed to open '{}'", path))?)
+        .read_to_end(&mut data)
+        .with_context(|| format!("failed to read '{}'", path))?;
+    if data.len() >= 4 && u32::from_le_bytes(data[0..4].try_into()?) == PCAPNG_SECTION_HEADER {
+        read_pcapng(&data)
+    } else {
+        read_pcap(&data)
+    }.with_context(|| format!("failed to parse '{}'", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A pcap file of raw IP holding one packet.
+    fn pcap(magic: u32, big_endian: bool, seconds: u32, fraction: u32, data: &[u8]) -> Vec<u8> {
+        let u16_bytes = |x: u16| if big_endian { x.to_be_bytes() } else { x.to_le_bytes() };
+        let u32_bytes = |x: u32| if big_endian { x.to_be_bytes() } else { x.to_le_bytes() };
+        let mut file: Vec<u8> = vec![];
+        file.extend(u32_bytes(magic));
+        file.extend(u16_bytes(2));
+        file.extend(u16_bytes(4));
+        for field in [0, 0, 65535, LINKTYPE_RAW, seconds, fraction, data.len() as u32, data.len() as u32] {
+            file.extend(u32_bytes(field));
+        }
+        file.extend_from_slice(data);
+        file
+    }
+
+    #[test]
+    fn reads_pcap_with_microsecond_timestamps() {
+        let packets = read_pcap(&pcap(PCAP_MAGIC_MICROS, false, 10, 250, &[1, 2, 3])).unwrap();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].timestamp, UNIX_EPOCH + Duration::from_secs(10) + Duration::from_micros(250));
+        assert_eq!(packets[0].linktype, LINKTYPE_RAW);
+        assert_eq!(packets[0].data, [1, 2, 3]);
+    }
+
+    #[test]
+    fn reads_big_endian_pcap_with_nanosecond_timestamps() {
+        let packets = read_pcap(&pcap(PCAP_MAGIC_NANOS, true, 10, 250, &[1, 2, 3])).unwrap();
+        assert_eq!(packets[0].timestamp, UNIX_EPOCH + Duration::from_secs(10) + Duration::from_nanos(250));
+        assert_eq!(packets[0].linktype, LINKTYPE_RAW);
+    }
+
+    #[test]
+    fn rejects_truncated_pcap() {
+        let mut file = pcap(PCAP_MAGIC_MICROS, false, 10, 250, &[1, 2, 3]);
+        file.pop();
+        assert!(read_pcap(&file).is_err());
+        assert!(read_pcap(&[0u8; 24]).is_err());
+    }
+
+    #[test]
+    fn interface_resolution_reads_tsresol() {
+        // if_tsresol of 10^-9, then the end of options.
+        assert_eq!(interface_resolution(&[9, 0, 1, 0, 9, 0, 0, 0, 0, 0, 0, 0], false), 1_000_000_000);
+        // 2^-10, big-endian, after an unrelated comment option.
+        assert_eq!(interface_resolution(&[0, 1, 0, 2, b'h', b'i', 0, 0, 0, 9, 0, 1, 0x8A, 0, 0, 0], true), 1024);
+        // Microseconds when the option is missing.
+        assert_eq!(interface_resolution(&[0, 0, 0, 0], false), 1_000_000);
+    }
+
+    #[test]
+    fn reads_pcapng_fixture() {
+        let packets = read_packets(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/read_holding_registers.pcapng")).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert!(packets.iter().all(|packet| packet.linktype == LINKTYPE_ETHERNET));
+        // The interface has nanosecond resolution.
+        assert_eq!(packets[0].timestamp, UNIX_EPOCH + Duration::from_nanos(1_666_156_892_123_456_789));
+        assert_eq!(packets[1].timestamp, UNIX_EPOCH + Duration::from_nanos(1_666_156_892_127_356_789));
+        // Ethernet, IPv4 and TCP headers, then the MBAP header of transaction 12 and a read of 2 registers from 100.
+        assert_eq!(packets[0].data.len(), 14 + 20 + 20 + 12);
+        assert_eq!(packets[0].data[54..], [0, 12, 0, 0, 0, 6, 1, 3, 0, 100, 0, 2]);
+    }
+
+    #[test]
+    fn pcapng_packet_needs_an_interface() {
+        let fixture = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/read_holding_registers.pcapng")).unwrap();
+        // Leave out the interface description block that follows the 28-byte section header.
+        let interface_len = u32_at(&fixture, 28 + 4, false) as usize;
+        let without_interface = [&fixture[..28], &fixture[28 + interface_len..]].concat();
+        assert!(read_pcapng(&without_interface).is_err());
+    }
+}
@@ -0,0 +1,41 @@
+use std::fs::File;
+use std::io::{BufWriter, Error, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::PCAP_MAGIC_MICROS;
+
+/// Largest packet a capture says it may hold.
+const SNAPLEN: u32 = 65535;
+
+/// Writes packets to a pcap file with microsecond timestamps.
+pub struct Writer {
+    file: BufWriter<File>,
+}
+
+impl Writer {
+    /// Start a capture of packets with the given link type, replacing `file`'s contents.
+    pub fn new(file: File, linktype: u32) -> Result<Writer, Error> {
+        let mut file = BufWriter::new(file);
+        file.write_all(&PCAP_MAGIC_MICROS.to_le_bytes())?;
+        file.write_all(&2u16.to_le_bytes())?;
+        file.write_all(&4u16.to_le_bytes())?;
+        // Timezone offset and timestamp accuracy, both always zero.
+        file.write_all(&[0u8; 8])?;
+        file.write_all(&SNAPLEN.to_le_bytes())?;
+        file.write_all(&linktype.to_le_bytes())?;
+        file.flush()?;
+        Ok(Writer { file })
+    }
+
+    /// Append a packet. Packets are flushed as they are written, so the capture survives mbc being interrupted.
+    pub fn write_packet(&mut self, timestamp: SystemTime, data: &[u8]) -> Result<(), Error> {
+        let since_epoch = timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let len = data.len() as u32;
+        self.file.write_all(&(since_epoch.as_secs() as u32).to_le_bytes())?;
+        self.file.write_all(&since_epoch.subsec_micros().to_le_bytes())?;
+        self.file.write_all(&len.to_le_bytes())?;
+        self.file.write_all(&len.to_le_bytes())?;
+        self.file.write_all(data)?;
+        self.file.flush()
+    }
+}
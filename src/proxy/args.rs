@@ -0,0 +1,71 @@
+
+use std::str::FromStr;
+use clap::Args;
+
+use crate::alarm;
+use crate::client::Table;
+use crate::mei;
+
+/// Forward Modbus/TCP traffic to the server given by the URI, logging every request and response.
+/// Requests can be refused by policy, in which case the proxy answers them with an exception
+#[derive(Args, Clone, Debug)]
+pub struct ProxyArgs {
+    /// address to accept client connections on
+    #[clap(long, value_parser, default_value = "0.0.0.0:502")]
+    pub listen: String,
+
+    /// refuse every write that isn't allowed with --allow-writes
+    #[clap(long)]
+    pub deny_writes: bool,
+
+    /// coils or holding registers that may be written, e.g. hr:100-120 or co:5. May be given several times, and
+    /// denies all other writes
+    #[clap(long, value_parser)]
+    pub allow_writes: Vec<WriteRange>,
+
+    /// function code to refuse, e.g. 0x08. May be given several times
+    #[clap(long, value_parser = mei::parse_byte)]
+    pub block_function: Vec<u8>,
+
+    /// most requests per second to relay for each client. Requests over the limit are answered with Server Device Busy
+    #[clap(long, value_parser = parse_rate)]
+    pub rate_limit: Option<f64>,
+}
+
+fn parse_rate(s: &str) -> Result<f64, String> {
+    let rate: f64 = s.parse().map_err(|e| format!("invalid rate '{}': {}", s, e))?;
+    if !rate.is_finite() || rate <= 0.0 {
+        return Err(format!("invalid rate '{}': must be a positive number", s));
+    }
+    Ok(rate)
+}
+
+/// A range of coils or holding registers, e.g. `hr:100-120`.
+#[derive(Clone, Copy, Debug)]
+pub struct WriteRange {
+    pub table: Table,
+    pub first: u16,
+    pub last: u16,
+}
+
+impl FromStr for WriteRange {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, last) = match s.split_once('-') {
+            Some((start, last)) => (start, Some(last)),
+            None => (s, None),
+        };
+        let (table, first) = alarm::parse_reference(start).map_err(|e| format!("invalid range '{}': {}", s, e))?;
+        if !matches!(table, Table::Coils | Table::HoldingRegisters) {
+            return Err(format!("invalid range '{}': only coils (co) and holding registers (hr) can be written", s));
+        }
+        let last = match last {
+            Some(last) => last.trim().parse::<u16>().map_err(|e| format!("invalid range '{}': {}", s, e))?,
+            None => first,
+        };
+        if last < first {
+            return Err(format!("invalid range '{}': the last address comes before the first", s));
+        }
+        Ok(WriteRange { table, first, last })
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime};
+use anyhow::{anyhow, Context, Error};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{self, UnboundedSender};
+
+use crate::frame::pdu::{self, Exchange};
+use crate::frame::tcp;
+use crate::output::Output;
+use crate::uri::{ModbusUri, Proto};
+
+pub mod args;
+mod policy;
+
+use policy::Policy;
+
+/// A request waiting for its response, by transaction id.
+struct Pending {
+    unit_id: u8,
+    pdu: Vec<u8>,
+    sent: SystemTime,
+    started: Instant,
+}
+
+/// Decision logged for requests that were relayed.
+const FORWARDED: &str = "forwarded";
+
+fn log_row(peer: SocketAddr, transaction_id: u16, unit_id: u8, sent: SystemTime, exchange: Exchange, rtt: String, decision: &str) -> Vec<String> {
+    let mut row = vec![
+        humantime::format_rfc3339_millis(sent).to_string(),
+        peer.to_string(),
+        unit_id.to_string(),
+        transaction_id.to_string(),
+    ];
+    row.extend(exchange.row());
+    row.push(rtt);
+    row.push(decision.to_string());
+    row
+}
+
+/// Relay one client's traffic to its own upstream connection. Requests and responses are relayed independently,
+/// so clients that pipeline requests keep working.
+/// Requests the policy refuses are answered by the proxy with an exception and never reach the server.
+async fn proxy_connection(client: TcpStream, peer: SocketAddr, upstream: String, mut policy: Policy, log: UnboundedSender<Vec<String>>) -> Result<(), Error> {
+    let upstream = TcpStream::connect(upstream.as_str())
+        .await
+        .with_context(|| format!("could not connect to `{}`", upstream))?;
+    let (mut client_rx, client_tx) = client.into_split();
+    // Refusals are answered from the request side, so both sides write to the client.
+    let client_tx = tokio::sync::Mutex::new(client_tx);
+    let (mut upstream_rx, mut upstream_tx) = upstream.into_split();
+    let pending: Mutex<HashMap<u16, Pending>> = Mutex::new(HashMap::new());
+
+    let requests = async {
+        while let Some((header, pdu)) = tcp::read_frame(&mut client_rx).await? {
+            if let Err(refusal) = policy.check(&pdu) {
+                let function = pdu.first().copied().unwrap_or_default();
+                let response = pdu::exception(function, refusal.exception);
+                client_tx.lock().await.write_all(&tcp::encode(header.transaction_id, header.unit_id, &response)).await?;
+                let exchange = Exchange::decode(Some(&pdu), Some(&response));
+                let decision = format!("refused: {}", refusal.reason);
+                let _ = log.send(log_row(peer, header.transaction_id, header.unit_id, SystemTime::now(), exchange, String::new(), &decision));
+                continue;
+            }
+            upstream_tx.write_all(&tcp::encode(header.transaction_id, header.unit_id, &pdu)).await?;
+            let request = Pending { unit_id: header.unit_id, pdu, sent: SystemTime::now(), started: Instant::now() };
+            pending.lock().unwrap().insert(header.transaction_id, request);
+        }
+        Ok::<(), Error>(())
+    };
+    let responses = async {
+        while let Some((header, pdu)) = tcp::read_frame(&mut upstream_rx).await? {
+            client_tx.lock().await.write_all(&tcp::encode(header.transaction_id, header.unit_id, &pdu)).await?;
+            let request = pending.lock().unwrap().remove(&header.transaction_id);
+            let row = match request {
+                Some(request) => log_row(
+                    peer, header.transaction_id, request.unit_id, request.sent,
+                    Exchange::decode(Some(&request.pdu), Some(&pdu)),
+                    format!("{:.1}", request.started.elapsed().as_secs_f64() * 1000.0),
+                    FORWARDED,
+                ),
+                None => log_row(peer, header.transaction_id, header.unit_id, SystemTime::now(), Exchange::decode(None, Some(&pdu)), String::new(), FORWARDED),
+            };
+            let _ = log.send(row);
+        }
+        Ok::<(), Error>(())
+    };
+    let result = tokio::select! {
+        result = requests => result,
+        result = responses => result,
+    };
+
+    for (transaction_id, request) in pending.into_inner().unwrap() {
+        let _ = log.send(log_row(peer, transaction_id, request.unit_id, request.sent, Exchange::decode(Some(&request.pdu), None), String::new(), FORWARDED));
+    }
+    result
+}
+
+async fn accept_clients(listener: TcpListener, upstream: String, policy: Policy, log: UnboundedSender<Vec<String>>) -> Result<(), Error> {
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        eprintln!("{} connected", peer);
+        let upstream = upstream.clone();
+        let policy = policy.clone();
+        let log = log.clone();
+        tokio::spawn(async move {
+            match proxy_connection(stream, peer, upstream, policy, log).await {
+                Ok(()) => eprintln!("{} disconnected", peer),
+                Err(e) => eprintln!("{} disconnected: {:#}", peer, e),
+            }
+        });
+    }
+}
+
+/// Accept clients and relay them to the upstream server until interrupted, writing each exchange to the output.
+pub async fn proxy_action(uri: ModbusUri, args: args::ProxyArgs, output: &mut dyn Output) -> Result<(), Error> {
+    if !matches!(uri.proto, Proto::Tcp) {
+        return Err(anyhow!("the proxy forwards to a tcp:// server, got `{}`", uri));
+    }
+    let upstream = format!("{}:{}", uri.host, uri.port);
+    let listener = TcpListener::bind(args.listen.as_str())
+        .await
+        .with_context(|| format!("could not listen on `{}`", args.listen))?;
+    eprintln!("proxying {} to {}", listener.local_addr()?, upstream);
+
+    let mut columns = vec![
+        "timestamp".to_string(),
+        "client".to_string(),
+        "unit_id".to_string(),
+        "transaction_id".to_string(),
+    ];
+    columns.extend(Exchange::columns());
+    columns.push("rtt_ms".to_string());
+    columns.push("decision".to_string());
+    output.write_header(&columns)?;
+
+    // Outputs can't be shared between tasks, so connections send their rows back here to be written.
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let accept = accept_clients(listener, upstream, Policy::new(&args), tx);
+    tokio::pin!(accept);
+    loop {
+        tokio::select! {
+            result = &mut accept => return result,
+            Some(row) = rx.recv() => output.write_rows(&[row])?,
+        }
+    }
+}
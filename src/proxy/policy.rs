@@ -0,0 +1,174 @@
+use std::time::Instant;
+
+use crate::client::{self, Table};
+use crate::frame::pdu::word;
+use super::args::{ProxyArgs, WriteRange};
+
+/// Why a request was refused, and the exception to answer it with.
+pub struct Refusal {
+    pub exception: u8,
+    pub reason: String,
+}
+
+/// The table, first address and quantity a request writes, if it writes to a table.
+fn written(pdu: &[u8]) -> Option<(Table, u16, u16)> {
+    match pdu.first()? {
+        0x05 => Some((Table::Coils, word(pdu, 1)?, 1)),
+        0x0F => Some((Table::Coils, word(pdu, 1)?, word(pdu, 3)?)),
+        0x06 | 0x16 => Some((Table::HoldingRegisters, word(pdu, 1)?, 1)),
+        0x10 => Some((Table::HoldingRegisters, word(pdu, 1)?, word(pdu, 3)?)),
+        0x17 => Some((Table::HoldingRegisters, word(pdu, 5)?, word(pdu, 7)?)),
+        _ => None,
+    }
+}
+
+fn is_write(function: u8) -> bool {
+    matches!(function, 0x05 | 0x06 | 0x0F | 0x10 | 0x15 | 0x16 | 0x17)
+}
+
+/// What a client may send through the proxy. Each connection gets its own copy, so rate limits apply per client.
+#[derive(Clone)]
+pub struct Policy {
+    deny_writes: bool,
+    allow_writes: Vec<WriteRange>,
+    block_function: Vec<u8>,
+    rate_limit: Option<f64>,
+    /// Requests that may still be sent right now, refilled at the rate limit up to one second's worth, or one request
+    /// for limits under one per second
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Policy {
+    pub fn new(args: &ProxyArgs) -> Policy {
+        Policy {
+            deny_writes: args.deny_writes || !args.allow_writes.is_empty(),
+            allow_writes: args.allow_writes.clone(),
+            block_function: args.block_function.clone(),
+            rate_limit: args.rate_limit,
+            tokens: args.rate_limit.map_or(0.0, |rate| rate.max(1.0)),
+            refilled: Instant::now(),
+        }
+    }
+
+    fn take_token(&mut self, rate: f64) -> bool {
+        let now = Instant::now();
+        self.tokens = (self.tokens + rate * now.duration_since(self.refilled).as_secs_f64()).min(rate.max(1.0));
+        self.refilled = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Decide whether a request may be relayed.
+    pub fn check(&mut self, pdu: &[u8]) -> Result<(), Refusal> {
+        let function = pdu.first().copied().unwrap_or_default();
+        if self.block_function.contains(&function) {
+            return Err(Refusal {
+                exception: client::ILLEGAL_FUNCTION,
+                reason: format!("function {:#04x} is blocked", function),
+            });
+        }
+        if self.deny_writes && is_write(function) {
+            let allowed = written(pdu).is_some_and(|(table, address, quantity)| {
+                let last = u32::from(address) + u32::from(quantity.max(1)) - 1;
+                self.allow_writes.iter().any(|range| {
+                    range.table == table && address >= range.first && last <= u32::from(range.last)
+                })
+            });
+            if !allowed {
+                return Err(Refusal {
+                    exception: client::ILLEGAL_DATA_ADDRESS,
+                    reason: "write outside the allowed ranges".to_string(),
+                });
+            }
+        }
+        if let Some(rate) = self.rate_limit {
+            if !self.take_token(rate) {
+                return Err(Refusal {
+                    exception: client::SERVER_DEVICE_BUSY,
+                    reason: "rate limited".to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+
+    fn policy(deny_writes: bool, allow_writes: &[&str], block_function: &[u8], rate_limit: Option<f64>) -> Policy {
+        Policy::new(&ProxyArgs {
+            listen: "127.0.0.1:502".to_string(),
+            deny_writes,
+            allow_writes: allow_writes.iter().map(|range| range.parse().unwrap()).collect(),
+            block_function: block_function.to_vec(),
+            rate_limit,
+        })
+    }
+
+    fn refused_with(policy: &mut Policy, pdu: &[u8]) -> Option<u8> {
+        policy.check(pdu).err().map(|refusal| refusal.exception)
+    }
+
+    #[test]
+    fn blocks_functions() {
+        let mut policy = policy(false, &[], &[0x08], None);
+        assert_eq!(refused_with(&mut policy, &[0x08, 0x00, 0x00, 0x12, 0x34]), Some(client::ILLEGAL_FUNCTION));
+        assert_eq!(refused_with(&mut policy, &[0x03, 0x00, 0x00, 0x00, 0x01]), None);
+    }
+
+    #[test]
+    fn deny_writes_refuses_every_write() {
+        let mut policy = policy(true, &[], &[], None);
+        assert_eq!(refused_with(&mut policy, &[0x06, 0x00, 0x01, 0x00, 0x05]), Some(client::ILLEGAL_DATA_ADDRESS));
+        assert_eq!(refused_with(&mut policy, &[0x15, 0x09, 0x06, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00]), Some(client::ILLEGAL_DATA_ADDRESS));
+        assert_eq!(refused_with(&mut policy, &[0x03, 0x00, 0x00, 0x00, 0x01]), None);
+    }
+
+    #[test]
+    fn allow_writes_admits_writes_inside_the_ranges() {
+        let mut policy = policy(false, &["hr:100-120", "co:5"], &[], None);
+        assert_eq!(refused_with(&mut policy, &[0x10, 0x00, 0x64, 0x00, 0x15, 0x2A]), None);
+        assert_eq!(refused_with(&mut policy, &[0x05, 0x00, 0x05, 0xFF, 0x00]), None);
+        // One register past the end, the wrong table, and a read/write whose write half is outside.
+        assert_eq!(refused_with(&mut policy, &[0x10, 0x00, 0x64, 0x00, 0x16, 0x2C]), Some(client::ILLEGAL_DATA_ADDRESS));
+        assert_eq!(refused_with(&mut policy, &[0x05, 0x00, 0x64, 0xFF, 0x00]), Some(client::ILLEGAL_DATA_ADDRESS));
+        assert_eq!(refused_with(&mut policy, &[0x17, 0x00, 0x64, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x02, 0x00, 0x00]), Some(client::ILLEGAL_DATA_ADDRESS));
+        // File records aren't covered by any range.
+        assert_eq!(refused_with(&mut policy, &[0x15, 0x09, 0x06, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00]), Some(client::ILLEGAL_DATA_ADDRESS));
+        // A truncated write can't be checked, so it's refused too.
+        assert_eq!(refused_with(&mut policy, &[0x06, 0x00]), Some(client::ILLEGAL_DATA_ADDRESS));
+    }
+
+    #[test]
+    fn rate_limit_refills_over_time() {
+        let mut policy = policy(false, &[], &[], Some(2.0));
+        assert_eq!(refused_with(&mut policy, &[0x03, 0x00, 0x00, 0x00, 0x01]), None);
+        assert_eq!(refused_with(&mut policy, &[0x03, 0x00, 0x00, 0x00, 0x01]), None);
+        assert_eq!(refused_with(&mut policy, &[0x03, 0x00, 0x00, 0x00, 0x01]), Some(client::SERVER_DEVICE_BUSY));
+        policy.refilled -= Duration::from_millis(500);
+        assert_eq!(refused_with(&mut policy, &[0x03, 0x00, 0x00, 0x00, 0x01]), None);
+        // A long pause doesn't bank more than a second's worth.
+        policy.refilled -= Duration::from_secs(10);
+        for _ in 0..2 {
+            assert_eq!(refused_with(&mut policy, &[0x03, 0x00, 0x00, 0x00, 0x01]), None);
+        }
+        assert_eq!(refused_with(&mut policy, &[0x03, 0x00, 0x00, 0x00, 0x01]), Some(client::SERVER_DEVICE_BUSY));
+    }
+
+    #[test]
+    fn rate_limit_under_one_per_second_admits_the_first_request() {
+        let mut policy = policy(false, &[], &[], Some(0.5));
+        assert_eq!(refused_with(&mut policy, &[0x03, 0x00, 0x00, 0x00, 0x01]), None);
+        assert_eq!(refused_with(&mut policy, &[0x03, 0x00, 0x00, 0x00, 0x01]), Some(client::SERVER_DEVICE_BUSY));
+        policy.refilled -= Duration::from_secs(2);
+        assert_eq!(refused_with(&mut policy, &[0x03, 0x00, 0x00, 0x00, 0x01]), None);
+    }
+}
@@ -1,14 +1,61 @@
 
+use std::time::Duration;
 use clap::{Args, Subcommand};
-use crate::client::DeviceIdentificationCode;
+use crate::alarm::{Alarms, Condition};
+use crate::client::{DeviceIdentificationCode, Table};
 
 /// Read status information from the remote bus
 #[derive(Args, Clone, Debug)]
 pub struct ReadArgs {
+    /// repeat the read at this interval, e.g. 500ms or 1m, keeping the connection open between samples
+    #[clap(long, global = true, value_parser = humantime::parse_duration)]
+    pub interval: Option<Duration>,
+
+    /// number of samples to take. Without it, polling continues until interrupted
+    #[clap(long, global = true, value_parser = clap::value_parser!(u64).range(1..))]
+    pub count: Option<u64>,
+
+    /// condition that raises a WARNING when it holds, e.g. 'hr:100 > 350'. Tables are co, di, ir and hr.
+    /// The process exits with the Nagios code of the worst status seen
+    #[clap(long, global = true, value_parser)]
+    pub warning: Vec<Condition>,
+
+    /// condition that raises a CRITICAL when it holds, e.g. 'co:12 == true'
+    #[clap(long, global = true, value_parser)]
+    pub critical: Vec<Condition>,
+
+    /// shell command to run when a condition is violated. MBC_STATUS and MBC_VIOLATIONS describe the violation
+    #[clap(long, global = true, value_parser)]
+    pub hook: Option<String>,
+
     #[clap(subcommand)]
     pub function: ReadFuncs,
 }
 
+impl ReadArgs {
+    /// Whether the read should be repeated rather than issued once.
+    pub fn is_polling(&self) -> bool {
+        self.interval.is_some() || self.count.is_some()
+    }
+
+    pub fn alarms(&self) -> Alarms {
+        Alarms { warning: self.warning.clone(), critical: self.critical.clone(), hook: self.hook.clone() }
+    }
+}
+
+impl ReadFuncs {
+    /// The data table read by this function and the address the read starts at, if it reads one.
+    pub fn table(&self) -> Option<(Table, u16)> {
+        match self {
+            ReadFuncs::Coils(args) => Some((Table::Coils, args.address)),
+            ReadFuncs::DiscreteInputs(args) => Some((Table::DiscreteInputs, args.address)),
+            ReadFuncs::InputRegisters(args) => Some((Table::InputRegisters, args.address)),
+            ReadFuncs::HoldingRegisters(args) => Some((Table::HoldingRegisters, args.address)),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Subcommand)]
 pub enum ReadFuncs {
     /// coil value(s)
@@ -26,7 +73,7 @@ pub enum ReadFuncs {
     /// Device Identification
     DeviceIdentification(DeviceIdentification),
     /// Server ID. Per the Modbus standard, this will only work on serial RTUs.
-    ServerID,
+    ServerID(ServerId),
 }
 
 #[derive(Args, Clone, Debug)]
@@ -57,7 +104,7 @@ pub struct FileReference {
 
 #[derive(Args, Clone, Debug)]
 pub struct QueueAddress {
-    /// file number
+    /// FIFO pointer address
     #[clap(value_parser)]
     pub pointer_address: u16,
 }
@@ -70,4 +117,15 @@ pub struct DeviceIdentification {
     /// object to be read
     #[clap(value_parser)]
     pub object_id: u8,
-}
\ No newline at end of file
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct ServerId {
+    /// length of the device-specific server id, in bytes
+    #[clap(long, value_parser, default_value_t = 1)]
+    pub server_id_length: usize,
+
+    /// render the additional data as ASCII rather than hex. Non-printable bytes are shown as '.'
+    #[clap(long)]
+    pub ascii: bool,
+}
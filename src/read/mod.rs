@@ -1,11 +1,22 @@
 use std::io::Error;
-use anyhow::Context;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use anyhow::{anyhow, Context};
+use tokio::sync::{mpsc, Semaphore};
+use tokio::time::MissedTickBehavior;
 
-use crate::client::ReaderExt;
+use crate::alarm::Status;
+use crate::client::{self, ReaderExt};
+use crate::frame::pdu;
+use crate::uri::ModbusUri;
+use crate::output::Output;
 use crate::CommandResult;
 
 pub mod args;
 
+/// Polling interval used when only a sample count is given.
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+
 pub async fn read_action(client: &mut dyn ReaderExt, args: args::ReadArgs) -> Result<CommandResult, Error>{
     match args.function {
         args::ReadFuncs::Coils(args) => {
@@ -75,14 +86,18 @@ pub async fn read_action(client: &mut dyn ReaderExt, args: args::ReadArgs) -> Re
             Ok(CommandResult { columns, rows })
         },
         args::ReadFuncs::FIFOQueue(args) => {
-            let queue_items = client.read_fifo_queue(args.pointer_address).await?;
+            let queue = client.read_fifo_queue(args.pointer_address).await?;
 
-            let rows: Vec<Vec<String>> = queue_items
+            let mut rows: Vec<Vec<String>> = queue.values
                 .iter()
                 .enumerate()
-                .map(|(i, &value)| vec![i.to_string(), format!("{:#04x}", value)])
+                .map(|(i, &value)| vec![i.to_string(), format!("{:#04x}", value), queue.fifo_count.to_string()])
                 .collect();
-            let columns = vec!["offset".to_string(), "value".to_string()];
+            // An empty queue still gets a row, so the count of 0 shows up in the output.
+            if rows.is_empty() {
+                rows.push(vec![String::new(), String::new(), queue.fifo_count.to_string()]);
+            }
+            let columns = vec!["offset".to_string(), "value".to_string(), "fifo_count".to_string()];
             Ok(CommandResult { columns, rows })
         },
         args::ReadFuncs::DeviceIdentification(args) => {
@@ -91,38 +106,175 @@ pub async fn read_action(client: &mut dyn ReaderExt, args: args::ReadArgs) -> Re
                 .iter()
                 .map(|o| vec![
                     o.id.to_string(),
-                    std::str::from_utf8(&o.value)
-                        .with_context(|| format!("failed to decode object as utf8"))
-                        .unwrap()
-                        .to_string(),
+                    o.name().to_string(),
+                    o.value_string(),
                     device_id.conformity_level.to_string()
                 ])
                 .collect();
             let columns = vec![
                 "object_id".to_string(),
+                "name".to_string(),
                 "value".to_string(),
                 "conformity_level".to_string()
             ];
             Ok(CommandResult { columns, rows })
         },
-        args::ReadFuncs::ServerID => {
-            let server_id_data = client.read_server_identification().await?;
+        args::ReadFuncs::ServerID(args) => {
+            let server_id = client.read_server_identification(args.server_id_length).await?;
 
-            let mut rows: Vec<Vec<String>> = vec![];
-            for i in (0..server_id_data.len()).step_by(16) {
-                let mut row: Vec<String> = vec![format!("{:#04}:", i)];
-                let mut row_data: Vec<String> = vec![];
-                for j in i..i+16 {
-                    if j > server_id_data.len() {
-                        break;
-                    }
-                    row_data.push(format!("{:X}", server_id_data[j]));
-                }
-                row.push(row_data.join(" "));
-                rows.push(row);
-            }
-            let columns = vec!["offset".to_string(), "value".to_string()];
+            let additional_data = if args.ascii {
+                server_id.additional_data
+                    .iter()
+                    .map(|&x| if x.is_ascii_graphic() || x == b' ' { x as char } else { '.' })
+                    .collect()
+            } else {
+                pdu::hex(&server_id.additional_data)
+            };
+            let rows = vec![vec![
+                server_id.byte_count.to_string(),
+                pdu::hex(&server_id.server_id),
+                server_id.run_indicator.to_string(),
+                additional_data,
+            ]];
+            let columns = vec![
+                "byte_count".to_string(),
+                "server_id".to_string(),
+                "run_indicator".to_string(),
+                "additional_data".to_string(),
+            ];
             Ok(CommandResult { columns, rows })
         },
     }
-}
\ No newline at end of file
+}
+
+/// Repeat a read on a fixed schedule, streaming each sample to the output as it arrives.
+/// Every row is prefixed with the time the sample was taken. Failed samples are reported and skipped.
+/// Each sample is checked against the alarm conditions, returning the worst status seen.
+pub async fn poll_action(client: &mut dyn ReaderExt, args: args::ReadArgs, output: &mut dyn Output) -> Result<Status, anyhow::Error> {
+    // Ticks are scheduled from the start time rather than the end of the previous sample, so slow
+    // responses don't make the samples drift. Ticks missed entirely are skipped rather than bunched up.
+    let mut interval = tokio::time::interval(args.interval.unwrap_or(DEFAULT_INTERVAL));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    let alarms = args.alarms();
+    let read = args.function.table();
+    let mut worst = Status::Ok;
+    let mut last_status = Status::Ok;
+
+    let mut header_written = false;
+    let mut samples: u64 = 0;
+    while args.count.is_none_or(|count| samples < count) {
+        interval.tick().await;
+        samples += 1;
+
+        let timestamp = humantime::format_rfc3339_millis(SystemTime::now()).to_string();
+        let result = match read_action(client, args.clone()).await {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("{} sample {} failed: {}", timestamp, samples, e);
+                continue;
+            },
+        };
+
+        if !alarms.is_empty() {
+            let status = alarms.check(read, &result);
+            // Only run the hook when the status changes, rather than for every sample of a lasting violation.
+            if status != Status::Ok && status != last_status {
+                alarms.run_hook(status, read, &result);
+            }
+            last_status = status;
+            worst = worst.worst(status);
+        }
+
+        if !header_written {
+            let mut columns = vec!["timestamp".to_string()];
+            columns.extend(result.columns);
+            output.write_header(&columns)?;
+            header_written = true;
+        }
+        let rows: Vec<Vec<String>> = result.rows
+            .into_iter()
+            .map(|row| {
+                let mut stamped = vec![timestamp.clone()];
+                stamped.extend(row);
+                stamped
+            })
+            .collect();
+        output.write_rows(&rows)?;
+    }
+    Ok(worst)
+}
+
+/// Run the same read against many devices concurrently, streaming each device's rows tagged with its URI as it finishes.
+/// A device that fails is reported without stopping the others, and an error listing the failures is returned at the end.
+pub async fn read_many_action(
+    targets: Vec<ModbusUri>,
+    terminal_id: u8,
+    args: args::ReadArgs,
+    parallelism: u16,
+    timeout: Duration,
+    output: &mut dyn Output,
+) -> Result<(), anyhow::Error> {
+    let permits = Arc::new(Semaphore::new(usize::from(parallelism)));
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    // Targets are only spawned once a permit is free, alongside the loop below that writes their results.
+    let spawned = targets.clone();
+    tokio::spawn(async move {
+        for target in spawned {
+            let permit = permits.clone().acquire_owned().await.unwrap();
+            let tx = tx.clone();
+            let args = args.clone();
+            tokio::spawn(async move {
+                let _permit = permit;
+                let device = target.to_string();
+                let read = async {
+                    let mut client = client::connect(target, terminal_id)
+                        .await
+                        .with_context(|| "could not connect")?;
+                    read_action(&mut client, args).await.map_err(anyhow::Error::from)
+                };
+                let result = match tokio::time::timeout(timeout, read).await {
+                    Ok(result) => result,
+                    Err(_) => Err(anyhow!("no response within {}", humantime::format_duration(timeout))),
+                };
+                // The receiver only goes away if writing the output failed, in which case there's nobody to tell.
+                let _ = tx.send((device, result));
+            });
+        }
+    });
+
+    let mut header_written = false;
+    let mut failures: Vec<String> = vec![];
+    while let Some((device, result)) = rx.recv().await {
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("{}: {:#}", device, e);
+                failures.push(device);
+                continue;
+            },
+        };
+
+        if !header_written {
+            let mut columns = vec!["device".to_string()];
+            columns.extend(result.columns);
+            output.write_header(&columns)?;
+            header_written = true;
+        }
+        let rows: Vec<Vec<String>> = result.rows
+            .into_iter()
+            .map(|row| {
+                let mut tagged = vec![device.clone()];
+                tagged.extend(row);
+                tagged
+            })
+            .collect();
+        output.write_rows(&rows)?;
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("{} of {} devices failed: {}", failures.len(), targets.len(), failures.join(", ")))
+    }
+}
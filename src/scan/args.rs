@@ -0,0 +1,309 @@
+
+use std::fmt;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+use std::time::Duration;
+use clap::{Args, Subcommand};
+use tokio_serial::{DataBits, Parity, StopBits};
+use crate::client::Table;
+
+/// Discover devices and what they support
+#[derive(Args, Clone, Debug)]
+pub struct ScanArgs {
+    #[clap(subcommand)]
+    pub function: ScanFuncs,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum ScanFuncs {
+    /// unit ids that respond on the bus or behind a gateway
+    Units(UnitScan),
+    /// readable address ranges of each table
+    Registers(RegisterScan),
+    /// which read function codes the device supports
+    Functions(FunctionScan),
+    /// Modbus/TCP servers across an IPv4 network. Needs no URI
+    Network(NetworkScan),
+    /// baud rate and framing of an unknown device on a serial port. Needs no URI
+    Serial(SerialScan),
+}
+
+impl ScanFuncs {
+    /// Whether the scan talks to the device given by the URI, rather than finding its own.
+    pub fn needs_connection(&self) -> bool {
+        !matches!(self, ScanFuncs::Network(_) | ScanFuncs::Serial(_))
+    }
+
+    /// Whether the scan asked for a new connection after each unanswered probe.
+    pub fn reconnects(&self) -> bool {
+        match self {
+            ScanFuncs::Units(args) => args.reconnect,
+            ScanFuncs::Registers(args) => args.reconnect,
+            ScanFuncs::Functions(args) => args.reconnect,
+            ScanFuncs::Network(_) | ScanFuncs::Serial(_) => false,
+        }
+    }
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct UnitScan {
+    /// first unit id to probe
+    #[clap(long, value_parser, default_value_t = 1)]
+    pub first: u8,
+
+    /// last unit id to probe
+    #[clap(long, value_parser, default_value_t = 247)]
+    pub last: u8,
+
+    /// how long to wait for each unit to answer, e.g. 200ms
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "200ms")]
+    pub timeout: Duration,
+
+    /// table to read a single address from as the probe
+    #[clap(long, value_enum, default_value_t = Table::HoldingRegisters)]
+    pub table: Table,
+
+    /// address to probe
+    #[clap(long, value_parser, default_value_t = 0)]
+    pub address: u16,
+
+    /// open a new connection after a probe goes unanswered. Over TCP, a late answer is otherwise read as the answer to
+    /// the next probe; on a serial line late answers are dropped without it
+    #[clap(long)]
+    pub reconnect: bool,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct RegisterScan {
+    /// table to scan. May be given several times, defaults to all four
+    #[clap(long, value_enum)]
+    pub table: Vec<Table>,
+
+    /// first address to scan
+    #[clap(long, value_parser, default_value_t = 0)]
+    pub first: u16,
+
+    /// last address to scan
+    #[clap(long, value_parser, default_value_t = 9999)]
+    pub last: u16,
+
+    /// addresses read per request before bisecting. Capped at 125 for registers and 2000 for coils and inputs
+    #[clap(long, value_parser = clap::value_parser!(u16).range(1..=2000), default_value_t = 125)]
+    pub block_size: u16,
+
+    /// how long to wait for each read. Devices that ignore bad addresses are treated as if they had refused them
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "1s")]
+    pub timeout: Duration,
+
+    /// open a new connection after a probe goes unanswered. Over TCP, a late answer is otherwise read as the answer to
+    /// the next probe; on a serial line late answers are dropped without it
+    #[clap(long)]
+    pub reconnect: bool,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct FunctionScan {
+    /// also probe the diagnostics sub-functions that only return data or counters
+    #[clap(long)]
+    pub diagnostics: bool,
+
+    /// how long to wait for each probe, e.g. 500ms
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "1s")]
+    pub timeout: Duration,
+
+    /// open a new connection after a probe goes unanswered. Over TCP, a late answer is otherwise read as the answer to
+    /// the next probe; on a serial line late answers are dropped without it
+    #[clap(long)]
+    pub reconnect: bool,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct NetworkScan {
+    /// IPv4 network to sweep in CIDR notation, e.g. 10.1.0.0/24, or a single address
+    #[clap(value_parser)]
+    pub network: Network,
+
+    /// TCP port to connect to
+    #[clap(long, value_parser, default_value_t = 502)]
+    pub port: u16,
+
+    /// unit id to probe each server with. May be given several times, the first that answers is reported
+    #[clap(long = "unit-id", value_parser, default_values_t = [255, 1])]
+    pub unit_ids: Vec<u8>,
+
+    /// how long to wait for a connection before assuming nothing is listening, e.g. 500ms
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "500ms")]
+    pub connect_timeout: Duration,
+
+    /// how long to wait for each probe once connected
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "1s")]
+    pub timeout: Duration,
+}
+
+/// An IPv4 network given in CIDR notation
+#[derive(Clone, Copy, Debug)]
+pub struct Network {
+    pub address: Ipv4Addr,
+    pub prefix_length: u8,
+}
+
+impl Network {
+    /// Host addresses of the network. The network and broadcast addresses are left out unless the network is too small
+    /// to have them.
+    pub fn hosts(&self) -> impl Iterator<Item = Ipv4Addr> {
+        let mask = u32::MAX.checked_shl(32 - u32::from(self.prefix_length)).unwrap_or(0);
+        let first = u32::from(self.address) & mask;
+        let last = first | !mask;
+        let (first, last) = if self.prefix_length < 31 { (first + 1, last - 1) } else { (first, last) };
+        (first..=last).map(Ipv4Addr::from)
+    }
+}
+
+impl FromStr for Network {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, prefix_length) = match s.split_once('/') {
+            Some((address, prefix_length)) => (address, prefix_length),
+            None => (s, "32"),
+        };
+        let address = address.parse::<Ipv4Addr>()
+            .map_err(|e| format!("invalid address '{}': {}", address, e))?;
+        let prefix_length = match prefix_length.parse::<u8>() {
+            Ok(n) if n <= 32 => n,
+            _ => return Err(format!("invalid prefix length '{}', expected 0 to 32", prefix_length)),
+        };
+        Ok(Network { address, prefix_length })
+    }
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct SerialScan {
+    /// serial device to probe, e.g. /dev/ttyUSB0
+    #[clap(value_parser)]
+    pub device: String,
+
+    /// baud rate to try. May be given several times, defaults to the common rates from 1200 to 115200
+    #[clap(long = "baud", value_parser, default_values_t = [1200, 2400, 4800, 9600, 19200, 38400, 57600, 115200])]
+    pub baud_rates: Vec<u32>,
+
+    /// data bits, parity and stop bits to try, e.g. 8E1. May be given several times
+    #[clap(long = "framing", value_parser, default_values = ["8E1", "8N2", "8N1", "8O1"])]
+    pub framings: Vec<Framing>,
+
+    /// unit id to probe. May be given several times. Without it, every id from --first to --last is tried
+    #[clap(long = "unit-id", value_parser)]
+    pub unit_ids: Vec<u8>,
+
+    /// first unit id to try when none is given
+    #[clap(long, value_parser, default_value_t = 1)]
+    pub first: u8,
+
+    /// last unit id to try when none is given
+    #[clap(long, value_parser, default_value_t = 247)]
+    pub last: u8,
+
+    /// how long to wait for each probe to be answered
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "200ms")]
+    pub timeout: Duration,
+}
+
+/// Character framing of a serial line, written the usual way, e.g. 8N1
+#[derive(Clone, Copy, Debug)]
+pub struct Framing {
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+}
+
+impl fmt::Display for Framing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let data_bits = match self.data_bits {
+            DataBits::Five => 5,
+            DataBits::Six => 6,
+            DataBits::Seven => 7,
+            DataBits::Eight => 8,
+        };
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Even => 'E',
+            Parity::Odd => 'O',
+        };
+        let stop_bits = match self.stop_bits {
+            StopBits::One => 1,
+            StopBits::Two => 2,
+        };
+        write!(f, "{}{}{}", data_bits, parity, stop_bits)
+    }
+}
+
+impl FromStr for Framing {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid framing '{}', expected data bits, parity and stop bits, e.g. 8E1", s);
+        let chars: Vec<char> = s.to_uppercase().chars().collect();
+        if chars.len() != 3 {
+            return Err(invalid());
+        }
+        let data_bits = match chars[0] {
+            '5' => DataBits::Five,
+            '6' => DataBits::Six,
+            '7' => DataBits::Seven,
+            '8' => DataBits::Eight,
+            _ => return Err(invalid()),
+        };
+        let parity = match chars[1] {
+            'N' => Parity::None,
+            'E' => Parity::Even,
+            'O' => Parity::Odd,
+            _ => return Err(invalid()),
+        };
+        let stop_bits = match chars[2] {
+            '1' => StopBits::One,
+            '2' => StopBits::Two,
+            _ => return Err(invalid()),
+        };
+        Ok(Framing { data_bits, parity, stop_bits })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hosts(network: &str) -> Vec<Ipv4Addr> {
+        network.parse::<Network>().unwrap().hosts().collect()
+    }
+
+    #[test]
+    fn hosts_leave_out_network_and_broadcast_addresses() {
+        let hosts = hosts("10.1.0.0/24");
+        assert_eq!(hosts.len(), 254);
+        assert_eq!(hosts.first(), Some(&Ipv4Addr::new(10, 1, 0, 1)));
+        assert_eq!(hosts.last(), Some(&Ipv4Addr::new(10, 1, 0, 254)));
+    }
+
+    #[test]
+    fn hosts_mask_the_given_address() {
+        assert_eq!(hosts("192.168.7.77/30"), [Ipv4Addr::new(192, 168, 7, 77), Ipv4Addr::new(192, 168, 7, 78)]);
+    }
+
+    #[test]
+    fn hosts_of_point_to_point_and_single_address_networks() {
+        assert_eq!(hosts("10.0.0.1/31"), [Ipv4Addr::new(10, 0, 0, 0), Ipv4Addr::new(10, 0, 0, 1)]);
+        assert_eq!(hosts("10.0.0.9"), [Ipv4Addr::new(10, 0, 0, 9)]);
+        assert_eq!(hosts("10.0.0.9/32"), [Ipv4Addr::new(10, 0, 0, 9)]);
+    }
+
+    #[test]
+    fn hosts_of_the_whole_address_space() {
+        let network: Network = "0.0.0.0/0".parse().unwrap();
+        assert_eq!(network.hosts().next(), Some(Ipv4Addr::new(0, 0, 0, 1)));
+    }
+
+    #[test]
+    fn rejects_invalid_networks() {
+        assert!("10.0.0.0/33".parse::<Network>().is_err());
+        assert!("10.0.0/24".parse::<Network>().is_err());
+        assert!("10.0.0.0/".parse::<Network>().is_err());
+    }
+}
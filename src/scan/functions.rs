@@ -0,0 +1,95 @@
+use std::time::Instant;
+use anyhow::Error;
+use tokio_modbus::prelude::Request;
+
+use crate::client::{self, ReaderExt};
+use crate::output::Output;
+use super::args::FunctionScan;
+
+const DIAGNOSTICS: u8 = 0x08;
+
+/// Read-type functions, each with the smallest request that is valid for it.
+const PROBES: [(u8, &str, &[u8]); 12] = [
+    (0x01, "read coils", &[0x00, 0x00, 0x00, 0x01]),
+    (0x02, "read discrete inputs", &[0x00, 0x00, 0x00, 0x01]),
+    (0x03, "read holding registers", &[0x00, 0x00, 0x00, 0x01]),
+    (0x04, "read input registers", &[0x00, 0x00, 0x00, 0x01]),
+    (0x07, "read exception status", &[]),
+    (0x0B, "get comm event counter", &[]),
+    (0x0C, "get comm event log", &[]),
+    (0x11, "report server id", &[]),
+    // Byte count, reference type 6, file 1, record 0, one record.
+    (0x14, "read file record", &[0x07, 0x06, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01]),
+    (0x18, "read fifo queue", &[0x00, 0x00]),
+    // Basic device identification, starting at the vendor name.
+    (0x2B, "read device identification", &[0x0E, 0x01, 0x00]),
+    (0x2B, "canopen general reference", &[0x0D]),
+];
+
+/// Diagnostics sub-functions that only echo data or return counters. Restarting communications, forcing listen only
+/// mode and clearing counters change the device's state, so they are never sent.
+const DIAGNOSTIC_PROBES: [(u16, &str); 10] = [
+    (0x00, "return query data"),
+    (0x02, "return diagnostic register"),
+    (0x0B, "return bus message count"),
+    (0x0C, "return bus communication error count"),
+    (0x0D, "return bus exception error count"),
+    (0x0E, "return server message count"),
+    (0x0F, "return server no response count"),
+    (0x10, "return server nak count"),
+    (0x11, "return server busy count"),
+    (0x12, "return bus character overrun count"),
+];
+
+/// Send a single probe, returning its outcome and any exception the device answered with.
+async fn probe(client: &mut dyn ReaderExt, function: u8, data: Vec<u8>, args: &FunctionScan) -> (String, String) {
+    match tokio::time::timeout(args.timeout, client.call(Request::Custom(function, data))).await {
+        Err(_) => ("timeout".to_string(), String::new()),
+        Ok(Ok(_)) => ("supported".to_string(), String::new()),
+        Ok(Err(e)) => match client::exception_code(&e) {
+            Some(client::ILLEGAL_FUNCTION) => ("illegal function".to_string(), String::new()),
+            // Any other exception means the function is known, the device just refused this particular request.
+            Some(code) => ("supported".to_string(), client::exception_name(code)),
+            None => ("error".to_string(), e.to_string()),
+        },
+    }
+}
+
+/// Probe each read-type function, and optionally the read-only diagnostics, reporting a row per probe as it completes.
+/// A late answer to a probe that timed out is dropped before the next probe on a serial line. Over TCP it takes
+/// --reconnect to keep it from being credited to the next function.
+pub async fn scan_functions(client: &mut dyn ReaderExt, args: FunctionScan, output: &mut dyn Output) -> Result<(), Error> {
+    let columns = vec![
+        "function".to_string(),
+        "name".to_string(),
+        "result".to_string(),
+        "detail".to_string(),
+        "response_time_ms".to_string(),
+    ];
+    output.write_header(&columns)?;
+
+    let mut probes: Vec<(String, String, u8, Vec<u8>)> = PROBES.iter()
+        .map(|&(function, name, data)| {
+            let label = match function {
+                0x2B => format!("{:#04x}/{:#04x}", function, data[0]),
+                _ => format!("{:#04x}", function),
+            };
+            (label, name.to_string(), function, data.to_vec())
+        })
+        .collect();
+    if args.diagnostics {
+        probes.extend(DIAGNOSTIC_PROBES.iter().map(|&(sub_function, name)| {
+            let mut data = sub_function.to_be_bytes().to_vec();
+            data.extend([0x00, 0x00]);
+            (format!("{:#04x}/{:#06x}", DIAGNOSTICS, sub_function), name.to_string(), DIAGNOSTICS, data)
+        }));
+    }
+
+    for (label, name, function, data) in probes {
+        let started = Instant::now();
+        let (result, detail) = probe(client, function, data, &args).await;
+        let elapsed = format!("{:.1}", started.elapsed().as_secs_f64() * 1000.0);
+        output.write_rows(&[vec![label, name, result, detail, elapsed]])?;
+    }
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use anyhow::{anyhow, Context, Error};
+
+use crate::client::ReaderExt;
+use crate::output::Output;
+
+pub mod args;
+mod functions;
+mod network;
+mod registers;
+mod serial;
+mod units;
+
+pub async fn scan_action(client: &mut dyn ReaderExt, args: args::ScanArgs, output: &mut dyn Output) -> Result<(), Error> {
+    match args.function {
+        args::ScanFuncs::Units(unit_args) => units::scan_units(client, unit_args, output)
+            .await
+            .with_context(|| "failed to scan unit ids"),
+        args::ScanFuncs::Registers(register_args) => registers::scan_registers(client, register_args, output)
+            .await
+            .with_context(|| "failed to scan registers"),
+        args::ScanFuncs::Functions(function_args) => functions::scan_functions(client, function_args, output)
+            .await
+            .with_context(|| "failed to scan functions"),
+        args::ScanFuncs::Network(_) | args::ScanFuncs::Serial(_) => Err(anyhow!("this scan doesn't use a connection")),
+    }
+}
+
+/// Run a scan that finds its own devices rather than talking to the one given by the URI.
+pub async fn standalone_scan_action(args: args::ScanArgs, parallelism: u16, output: &mut dyn Output) -> Result<(), Error> {
+    match args.function {
+        args::ScanFuncs::Network(network_args) => network::scan_network(network_args, parallelism, output)
+            .await
+            .with_context(|| "failed to scan network"),
+        args::ScanFuncs::Serial(serial_args) => serial::scan_serial(serial_args, output)
+            .await
+            .with_context(|| "failed to scan serial port"),
+        _ => Err(anyhow!("this scan needs a URI to connect to")),
+    }
+}
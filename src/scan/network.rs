@@ -0,0 +1,131 @@
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Error};
+use tokio::sync::{mpsc, Semaphore};
+use tokio_modbus::prelude::Slave;
+
+use crate::client::{self, DeviceIdentificationCode, ReaderExt};
+use crate::output::Output;
+use crate::uri::{ModbusUri, Proto};
+use super::args::NetworkScan;
+
+/// What a server answered with for the first unit id that responded.
+struct Inventory {
+    unit_id: u8,
+    vendor: String,
+    product: String,
+    revision: String,
+    registers: String,
+}
+
+/// Describe the outcome of a probe, or None if it timed out.
+fn outcome<T>(result: Result<Result<T, std::io::Error>, tokio::time::error::Elapsed>) -> Option<Result<T, String>> {
+    match result {
+        Err(_) => None,
+        Ok(Ok(value)) => Some(Ok(value)),
+        Ok(Err(e)) => Some(Err(match client::exception_code(&e) {
+            Some(code) => client::exception_name(code),
+            None => e.to_string(),
+        })),
+    }
+}
+
+/// Identify the server with each unit id in turn until one answers either probe. The connection is opened again after a
+/// probe times out, so a late answer isn't taken for the next probe's.
+async fn probe(client: &mut dyn ReaderExt, args: &NetworkScan) -> Option<Inventory> {
+    for &unit_id in &args.unit_ids {
+        client.set_slave(Slave(unit_id));
+        let identification = outcome(tokio::time::timeout(
+            args.timeout,
+            client.read_device_identification(DeviceIdentificationCode::Basic, 0),
+        ).await);
+        let registers = outcome(tokio::time::timeout(
+            args.timeout,
+            client.read_holding_registers(0, 1),
+        ).await);
+        if identification.is_none() && registers.is_none() {
+            continue;
+        }
+
+        let object = |id: u8| match &identification {
+            Some(Ok(device_id)) => device_id.objects
+                .iter()
+                .find(|o| o.id == id)
+                .map(|o| o.value_string())
+                .unwrap_or_default(),
+            _ => String::new(),
+        };
+        let registers = match registers {
+            Some(Ok(_)) => "readable".to_string(),
+            Some(Err(e)) => e,
+            None => "timeout".to_string(),
+        };
+        return Some(Inventory {
+            unit_id,
+            vendor: object(0),
+            product: object(1),
+            revision: object(2),
+            registers,
+        });
+    }
+    None
+}
+
+/// Connect to every host of the network concurrently, writing a row for each server that accepts the connection.
+/// Servers that accept but don't answer any probe are still listed, without a unit id.
+pub async fn scan_network(args: NetworkScan, parallelism: u16, output: &mut dyn Output) -> Result<(), Error> {
+    let columns = vec![
+        "ip".to_string(),
+        "unit_id".to_string(),
+        "vendor".to_string(),
+        "product".to_string(),
+        "revision".to_string(),
+        "registers".to_string(),
+        "latency_ms".to_string(),
+    ];
+    output.write_header(&columns)?;
+
+    let args = Arc::new(args);
+    let permits = Arc::new(Semaphore::new(usize::from(parallelism)));
+    let (tx, mut rx) = mpsc::unbounded_channel::<(Ipv4Addr, Duration, Option<Inventory>)>();
+    // Hosts are only spawned once a permit is free, so large networks don't start a task per host up front.
+    // Spawning runs alongside the loop below, so rows are written as they come in.
+    let spawner_args = args.clone();
+    tokio::spawn(async move {
+        for host in spawner_args.network.hosts() {
+            let permit = permits.clone().acquire_owned().await.unwrap();
+            let tx = tx.clone();
+            let args = spawner_args.clone();
+            tokio::spawn(async move {
+                let _permit = permit;
+                let started = Instant::now();
+                let uri = ModbusUri { proto: Proto::Tcp, host: host.to_string(), port: u32::from(args.port) };
+                let mut client = match tokio::time::timeout(args.connect_timeout, client::connect_probing(uri, 0xFF, true)).await {
+                    Ok(Ok(client)) => client,
+                    _ => return,
+                };
+                let latency = started.elapsed();
+                let inventory = probe(&mut client, &args).await;
+                // The receiver only goes away if writing the output failed, in which case there's nobody to tell.
+                let _ = tx.send((host, latency, inventory));
+            });
+        }
+    });
+
+    let mut found = 0;
+    while let Some((host, latency, inventory)) = rx.recv().await {
+        found += 1;
+        let latency = format!("{:.1}", latency.as_secs_f64() * 1000.0);
+        let row = match inventory {
+            Some(i) => vec![host.to_string(), i.unit_id.to_string(), i.vendor, i.product, i.revision, i.registers, latency],
+            None => vec![host.to_string(), String::new(), String::new(), String::new(), String::new(), "no response".to_string(), latency],
+        };
+        output.write_rows(&[row])?;
+    }
+
+    if found == 0 {
+        return Err(anyhow!("no servers found on port {} of {}/{}", args.port, args.network.address, args.network.prefix_length));
+    }
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use anyhow::{Context, Error};
+
+use crate::client::{self, ReaderExt, Table};
+use crate::output::Output;
+use super::args::RegisterScan;
+
+/// Most registers a single read may return.
+const MAX_REGISTERS: u16 = 125;
+/// Most coils or discrete inputs a single read may return.
+const MAX_BITS: u16 = 2000;
+
+/// A run of consecutive readable addresses, with `end` one past the last address.
+struct Range {
+    start: u32,
+    end: u32,
+}
+
+fn range_row(table: Table, range: &Range) -> Vec<String> {
+    vec![
+        table.to_string(),
+        range.start.to_string(),
+        (range.end - 1).to_string(),
+        (range.end - range.start).to_string(),
+    ]
+}
+
+/// Walk one table in blocks, bisecting every block the device refuses until the readable addresses are pinned down.
+/// Blocks are visited in address order, so readable blocks can be merged into ranges and written as soon as a gap shows up.
+/// A read that times out ends the table, writing the ranges found so far.
+async fn scan_table(client: &mut dyn ReaderExt, table: Table, args: &RegisterScan, output: &mut dyn Output) -> Result<(), Error> {
+    let block_size = u32::from(args.block_size.min(if table.is_bits() { MAX_BITS } else { MAX_REGISTERS }));
+    let last = u32::from(args.last);
+    let mut current: Option<Range> = None;
+
+    let mut start = u32::from(args.first);
+    'blocks: while start <= last {
+        let length = block_size.min(last + 1 - start);
+        // Halves are pushed right first so the left half is read next.
+        let mut pending = vec![(start, length)];
+        while let Some((address, quantity)) = pending.pop() {
+            let read = client.read_table(table, address as u16, quantity as u16);
+            let refused = match tokio::time::timeout(args.timeout, read).await {
+                Ok(Ok(_)) => false,
+                // Bisecting a unit that has stopped answering would only wait out the timeout thousands of times.
+                Err(_) => {
+                    eprintln!("{}: no answer to a read of {} x{}, skipping the rest of the table", table, address, quantity);
+                    break 'blocks;
+                },
+                Ok(Err(e)) => match client::exception_code(&e) {
+                    Some(client::ILLEGAL_DATA_ADDRESS) | Some(client::ILLEGAL_DATA_VALUE) => true,
+                    Some(client::ILLEGAL_FUNCTION) => {
+                        eprintln!("{}: not supported by the device, skipping", table);
+                        return Ok(());
+                    },
+                    _ => return Err(e).with_context(|| format!("failed to read {} {} x{}", table, address, quantity)),
+                },
+            };
+
+            if refused {
+                if quantity > 1 {
+                    let half = quantity / 2;
+                    pending.push((address + half, quantity - half));
+                    pending.push((address, half));
+                }
+                continue;
+            }
+            match current.as_mut() {
+                Some(range) if range.end == address => range.end += quantity,
+                _ => {
+                    if let Some(range) = current.take() {
+                        output.write_rows(&[range_row(table, &range)])?;
+                    }
+                    current = Some(Range { start: address, end: address + quantity });
+                },
+            }
+        }
+        start += length;
+    }
+
+    if let Some(range) = current {
+        output.write_rows(&[range_row(table, &range)])?;
+    }
+    Ok(())
+}
+
+/// Find the contiguous readable address ranges of each requested table.
+pub async fn scan_registers(client: &mut dyn ReaderExt, args: RegisterScan, output: &mut dyn Output) -> Result<(), Error> {
+    let columns = vec![
+        "table".to_string(),
+        "first".to_string(),
+        "last".to_string(),
+        "count".to_string(),
+    ];
+    output.write_header(&columns)?;
+
+    let tables = if args.table.is_empty() {
+        vec![Table::Coils, Table::DiscreteInputs, Table::InputRegisters, Table::HoldingRegisters]
+    } else {
+        args.table.clone()
+    };
+    for table in tables {
+        scan_table(client, table, &args, output).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, Context, Error};
+use tokio::io::AsyncWriteExt;
+use tokio_serial::{ClearBuffer, SerialPort, SerialStream};
+
+use crate::client;
+use crate::frame::rtu;
+use crate::output::Output;
+use super::args::{Framing, SerialScan};
+
+/// Read one holding register at address 0.
+const PROBE_PDU: [u8; 5] = [0x03, 0x00, 0x00, 0x00, 0x01];
+/// USB serial adapters batch incoming bytes, so a frame can pause for longer than the spec's gap.
+const MIN_READ_GAP: Duration = Duration::from_millis(20);
+
+/// Probe each unit id with one setting until one answers with a frame that passes the CRC check.
+/// Returns the unit id, its answer and the response time.
+async fn probe_setting(port: &mut SerialStream, baud_rate: u32, unit_ids: &[u8], timeout: Duration) -> Result<Option<(u8, String, Duration)>, Error> {
+    let gap = rtu::frame_gap(baud_rate).max(MIN_READ_GAP);
+    for &unit_id in unit_ids {
+        // Drop anything left over from a previous probe, so it isn't mistaken for this one's answer.
+        port.clear(ClearBuffer::Input)?;
+        let started = Instant::now();
+        port.write_all(&rtu::encode(unit_id, &PROBE_PDU)).await?;
+        let frame = rtu::read_frame(port, Some(timeout), gap).await?;
+        let elapsed = started.elapsed();
+
+        let (responder, pdu) = match rtu::decode(&frame) {
+            Some(decoded) => decoded,
+            None => continue,
+        };
+        if responder != unit_id || pdu.is_empty() {
+            continue;
+        }
+        let answer = match pdu[0] {
+            0x03 => "ok".to_string(),
+            0x83 if pdu.len() > 1 => client::exception_name(pdu[1]),
+            _ => continue,
+        };
+        return Ok(Some((unit_id, answer, elapsed)));
+    }
+    Ok(None)
+}
+
+fn open(args: &SerialScan, baud_rate: u32, framing: Framing) -> Result<SerialStream, Error> {
+    let builder = tokio_serial::new(args.device.as_str(), baud_rate)
+        .data_bits(framing.data_bits)
+        .parity(framing.parity)
+        .stop_bits(framing.stop_bits);
+    SerialStream::open(&builder).with_context(|| format!("could not open `{}` at {} {}", args.device, baud_rate, framing))
+}
+
+/// Try every combination of baud rate and framing, writing a row for each one a device answers with a valid frame.
+pub async fn scan_serial(args: SerialScan, output: &mut dyn Output) -> Result<(), Error> {
+    let columns = vec![
+        "baud_rate".to_string(),
+        "framing".to_string(),
+        "unit_id".to_string(),
+        "response".to_string(),
+        "response_time_ms".to_string(),
+    ];
+    output.write_header(&columns)?;
+
+    let unit_ids: Vec<u8> = if args.unit_ids.is_empty() {
+        (args.first..=args.last).collect()
+    } else {
+        args.unit_ids.clone()
+    };
+
+    let mut found = 0;
+    for &baud_rate in &args.baud_rates {
+        for &framing in &args.framings {
+            eprintln!("trying {} {}", baud_rate, framing);
+            let mut port = open(&args, baud_rate, framing)?;
+            if let Some((unit_id, answer, elapsed)) = probe_setting(&mut port, baud_rate, &unit_ids, args.timeout).await? {
+                found += 1;
+                output.write_rows(&[vec![
+                    baud_rate.to_string(),
+                    framing.to_string(),
+                    unit_id.to_string(),
+                    answer,
+                    format!("{:.1}", elapsed.as_secs_f64() * 1000.0),
+                ]])?;
+            }
+        }
+    }
+
+    if found == 0 {
+        return Err(anyhow!("no valid responses on `{}` with any of the settings tried", args.device));
+    }
+    Ok(())
+}
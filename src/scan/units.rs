@@ -0,0 +1,44 @@
+use std::time::Instant;
+use anyhow::Error;
+use tokio_modbus::prelude::Slave;
+
+use crate::client::{self, ReaderExt};
+use crate::frame::pdu;
+use crate::output::Output;
+use super::args::UnitScan;
+
+/// Probe each unit id in turn over the existing connection, writing a row for every unit that answers.
+/// A late answer from a unit that timed out is dropped before the next probe on a serial line. Over TCP it takes
+/// --reconnect to keep it from being credited to the next unit.
+/// An exception response still means the unit is there, so it is reported alongside the exception.
+pub async fn scan_units(client: &mut dyn ReaderExt, args: UnitScan, output: &mut dyn Output) -> Result<(), Error> {
+    let columns = vec![
+        "unit_id".to_string(),
+        "response_time_ms".to_string(),
+        "exception".to_string(),
+    ];
+    output.write_header(&columns)?;
+
+    for unit_id in args.first..=args.last {
+        client.set_slave(Slave(unit_id));
+        let started = Instant::now();
+        let result = tokio::time::timeout(args.timeout, client.read_table(args.table, args.address, 1)).await;
+        let elapsed = format!("{:.1}", started.elapsed().as_secs_f64() * 1000.0);
+
+        let exception = match result {
+            Err(_) => continue,
+            Ok(Ok(_)) => String::new(),
+            Ok(Err(e)) => match client::exception_code(&e) {
+                // A gateway answering for a unit that isn't there doesn't make the unit respond.
+                Some(pdu::GATEWAY_PATH_UNAVAILABLE) | Some(pdu::GATEWAY_TARGET_FAILED) => continue,
+                Some(code) => client::exception_name(code),
+                None => {
+                    eprintln!("unit {}: {}", unit_id, e);
+                    continue;
+                },
+            },
+        };
+        output.write_rows(&[vec![unit_id.to_string(), elapsed, exception]])?;
+    }
+    Ok(())
+}
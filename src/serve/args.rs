@@ -0,0 +1,18 @@
+
+use clap::Args;
+
+/// Simulate a device, answering requests from memory. The URI is the address to listen on
+#[derive(Args, Clone, Debug)]
+pub struct ServeArgs {
+    /// JSON file with the initial contents of the device
+    #[clap(long, value_parser)]
+    pub definition: Option<String>,
+
+    /// JSON file listing faults to inject, such as delays, dropped responses and exceptions
+    #[clap(long, value_parser)]
+    pub faults: Option<String>,
+
+    /// unit id to answer for. May be given several times, defaults to answering every unit id
+    #[clap(long = "unit-id", value_parser)]
+    pub unit_ids: Vec<u8>,
+}
@@ -0,0 +1,605 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use anyhow::{Context, Error};
+use serde::Deserialize;
+
+use crate::client::{Table, ILLEGAL_DATA_ADDRESS, ILLEGAL_DATA_VALUE, ILLEGAL_FUNCTION};
+use crate::frame::pdu;
+use super::generator::GeneratorDefinition;
+use super::rule::{Reference, Rule};
+
+/// Every table covers the whole address space.
+const TABLE_SIZE: usize = 0x10000;
+/// Each file holds records 0 through 9999.
+const FILE_RECORDS: usize = 10000;
+/// A FIFO queue holds at most 31 registers.
+const MAX_FIFO_COUNT: usize = 31;
+
+/// Server identification reported by Report Server ID
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerIdDefinition {
+    pub server_id: Vec<u8>,
+    pub run_indicator: bool,
+    pub additional_data: String,
+}
+
+impl Default for ServerIdDefinition {
+    fn default() -> Self {
+        ServerIdDefinition { server_id: vec![0x01], run_indicator: true, additional_data: "mbc".to_string() }
+    }
+}
+
+/// Initial contents and behavior of a simulated device. Tables, files and FIFO queues are keyed by their first address
+/// and hold consecutive values; device identification objects are keyed by object id. Generators are keyed by the
+/// address they drive, and rules are applied in order.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Definition {
+    pub coils: BTreeMap<u16, Vec<bool>>,
+    pub discrete_inputs: BTreeMap<u16, Vec<bool>>,
+    pub input_registers: BTreeMap<u16, Vec<u16>>,
+    pub holding_registers: BTreeMap<u16, Vec<u16>>,
+    pub files: BTreeMap<u16, Vec<u16>>,
+    pub fifos: BTreeMap<u16, Vec<u16>>,
+    pub device_identification: BTreeMap<u8, String>,
+    pub server_id: ServerIdDefinition,
+    pub generators: BTreeMap<Reference, GeneratorDefinition>,
+    pub rules: Vec<Rule>,
+}
+
+impl Definition {
+    pub fn load(path: &str) -> Result<Definition, Error> {
+        let file = File::open(path).with_context(|| format!("failed to open '{}'", path))?;
+        serde_json::from_reader(file).with_context(|| format!("failed to parse '{}'", path))
+    }
+}
+
+fn fill<T: Copy>(table: &mut [T], blocks: &BTreeMap<u16, Vec<T>>) {
+    for (&start, values) in blocks {
+        let start = usize::from(start);
+        let end = (start + values.len()).min(table.len());
+        table[start..end].copy_from_slice(&values[..end - start]);
+    }
+}
+
+fn pack_bits(bits: &[bool]) -> Vec<u8> {
+    bits.chunks(8)
+        .map(|chunk| chunk.iter().enumerate().fold(0u8, |byte, (i, &bit)| byte | (u8::from(bit) << i)))
+        .collect()
+}
+
+fn word(pdu: &[u8], offset: usize) -> Result<u16, u8> {
+    pdu::word(pdu, offset).ok_or(ILLEGAL_DATA_VALUE)
+}
+
+/// Check a range against the address space, returning it as indexes.
+fn range(address: u16, quantity: u16, max_quantity: u16) -> Result<std::ops::Range<usize>, u8> {
+    if quantity == 0 || quantity > max_quantity {
+        return Err(ILLEGAL_DATA_VALUE);
+    }
+    let start = usize::from(address);
+    let end = start + usize::from(quantity);
+    if end > TABLE_SIZE {
+        return Err(ILLEGAL_DATA_ADDRESS);
+    }
+    Ok(start..end)
+}
+
+/// Counters reported by the diagnostics functions.
+#[derive(Default)]
+struct Counters {
+    bus_messages: u16,
+    bus_exception_errors: u16,
+    server_messages: u16,
+}
+
+/// The state of a simulated device, and how it answers requests.
+pub struct Device {
+    pub coils: Vec<bool>,
+    pub discrete_inputs: Vec<bool>,
+    pub input_registers: Vec<u16>,
+    pub holding_registers: Vec<u16>,
+    pub files: BTreeMap<u16, Vec<u16>>,
+    pub fifos: BTreeMap<u16, Vec<u16>>,
+    device_identification: BTreeMap<u8, String>,
+    server_id: ServerIdDefinition,
+    rules: Vec<Rule>,
+    /// Whether each rule's condition held when the rules were last applied.
+    rules_held: Vec<bool>,
+    counters: Counters,
+}
+
+/// The address a rule's condition looks at.
+fn rule_reference(rule: &Rule) -> Reference {
+    Reference { table: rule.when.0.table, address: rule.when.0.address }
+}
+
+impl Device {
+    pub fn new(definition: Definition) -> Device {
+        let mut device = Device {
+            coils: vec![false; TABLE_SIZE],
+            discrete_inputs: vec![false; TABLE_SIZE],
+            input_registers: vec![0; TABLE_SIZE],
+            holding_registers: vec![0; TABLE_SIZE],
+            files: definition.files.into_iter()
+                .map(|(number, mut records)| {
+                    records.resize(FILE_RECORDS, 0);
+                    (number, records)
+                })
+                .collect(),
+            fifos: definition.fifos.into_iter()
+                .map(|(address, mut values)| {
+                    values.truncate(MAX_FIFO_COUNT);
+                    (address, values)
+                })
+                .collect(),
+            device_identification: definition.device_identification,
+            server_id: definition.server_id,
+            rules: definition.rules,
+            rules_held: vec![],
+            counters: Counters::default(),
+        };
+        fill(&mut device.coils, &definition.coils);
+        fill(&mut device.discrete_inputs, &definition.discrete_inputs);
+        fill(&mut device.input_registers, &definition.input_registers);
+        fill(&mut device.holding_registers, &definition.holding_registers);
+        if device.device_identification.is_empty() {
+            device.device_identification = BTreeMap::from([
+                (0x00, "mbc".to_string()),
+                (0x01, "mbc serve".to_string()),
+                (0x02, env!("CARGO_PKG_VERSION").to_string()),
+            ]);
+        }
+        // Conditions that already hold in the initial contents don't fire.
+        device.rules_held = device.rules.iter()
+            .map(|rule| rule.when.0.holds(device.get(rule_reference(rule))))
+            .collect();
+        device
+    }
+
+    pub fn get(&self, reference: Reference) -> u16 {
+        let index = usize::from(reference.address);
+        match reference.table {
+            Table::Coils => u16::from(self.coils[index]),
+            Table::DiscreteInputs => u16::from(self.discrete_inputs[index]),
+            Table::InputRegisters => self.input_registers[index],
+            Table::HoldingRegisters => self.holding_registers[index],
+        }
+    }
+
+    fn set(&mut self, reference: Reference, value: u16) {
+        let index = usize::from(reference.address);
+        match reference.table {
+            Table::Coils => self.coils[index] = value != 0,
+            Table::DiscreteInputs => self.discrete_inputs[index] = value != 0,
+            Table::InputRegisters => self.input_registers[index] = value,
+            Table::HoldingRegisters => self.holding_registers[index] = value,
+        }
+    }
+
+    /// Set an address from outside the protocol, e.g. from a generator, and apply the rules.
+    pub fn update(&mut self, reference: Reference, value: u16) {
+        self.set(reference, value);
+        self.apply_rules();
+    }
+
+    /// Apply every rule whose condition has come to hold since the rules were last applied, in order, so a rule sees
+    /// what earlier rules set. A rule fires once when its condition becomes true, and again only after it was false.
+    fn apply_rules(&mut self) {
+        for i in 0..self.rules.len() {
+            let holds = self.rules[i].when.0.holds(self.get(rule_reference(&self.rules[i])));
+            let held = std::mem::replace(&mut self.rules_held[i], holds);
+            if !holds || held {
+                continue;
+            }
+            let assignments: Vec<(Reference, u16)> = self.rules[i].set.iter().map(|(&r, &v)| (r, v)).collect();
+            for (reference, value) in assignments {
+                self.set(reference, value);
+            }
+        }
+    }
+
+    /// Answer a request PDU with a response PDU, which is an exception response if the request can't be served.
+    pub fn handle(&mut self, request: &[u8]) -> Vec<u8> {
+        self.counters.bus_messages = self.counters.bus_messages.wrapping_add(1);
+        let function = match request.first() {
+            Some(&function) => function,
+            None => return vec![],
+        };
+        match self.dispatch(function, request) {
+            Ok(response) => {
+                self.counters.server_messages = self.counters.server_messages.wrapping_add(1);
+                if matches!(function, 0x05 | 0x06 | 0x0F | 0x10 | 0x16 | 0x17) {
+                    self.apply_rules();
+                }
+                response
+            },
+            Err(code) => {
+                self.counters.bus_exception_errors = self.counters.bus_exception_errors.wrapping_add(1);
+                vec![function | 0x80, code]
+            },
+        }
+    }
+
+    fn dispatch(&mut self, function: u8, pdu: &[u8]) -> Result<Vec<u8>, u8> {
+        match function {
+            0x01 | 0x02 => {
+                let range = range(word(pdu, 1)?, word(pdu, 3)?, 2000)?;
+                let table = if function == 0x01 { &self.coils } else { &self.discrete_inputs };
+                let bytes = pack_bits(&table[range]);
+                let mut response = vec![function, bytes.len() as u8];
+                response.extend(bytes);
+                Ok(response)
+            },
+            0x03 | 0x04 => {
+                let range = range(word(pdu, 1)?, word(pdu, 3)?, 125)?;
+                let table = if function == 0x03 { &self.holding_registers } else { &self.input_registers };
+                Ok(register_response(function, &table[range]))
+            },
+            0x05 => {
+                let address = word(pdu, 1)?;
+                self.coils[usize::from(address)] = match word(pdu, 3)? {
+                    0xFF00 => true,
+                    0x0000 => false,
+                    _ => return Err(ILLEGAL_DATA_VALUE),
+                };
+                Ok(pdu[..5].to_vec())
+            },
+            0x06 => {
+                let address = word(pdu, 1)?;
+                self.holding_registers[usize::from(address)] = word(pdu, 3)?;
+                Ok(pdu[..5].to_vec())
+            },
+            0x07 => Ok(vec![function, 0x00]),
+            0x08 => self.diagnostics(pdu),
+            // Status is never busy, and the event count is the number of requests served.
+            0x0B => Ok([[function].as_slice(), &0u16.to_be_bytes(), &self.counters.server_messages.to_be_bytes()].concat()),
+            0x0C => {
+                let mut response = vec![function, 6];
+                response.extend(0u16.to_be_bytes());
+                response.extend(self.counters.server_messages.to_be_bytes());
+                response.extend(self.counters.bus_messages.to_be_bytes());
+                Ok(response)
+            },
+            0x0F => {
+                let address = word(pdu, 1)?;
+                let range = range(address, word(pdu, 3)?, 1968)?;
+                let values = pdu.get(6..).ok_or(ILLEGAL_DATA_VALUE)?;
+                if usize::from(pdu[5]) != range.len().div_ceil(8) || values.len() != usize::from(pdu[5]) {
+                    return Err(ILLEGAL_DATA_VALUE);
+                }
+                for (i, index) in range.enumerate() {
+                    self.coils[index] = values[i / 8] & (1 << (i % 8)) != 0;
+                }
+                Ok(pdu[..5].to_vec())
+            },
+            0x10 => {
+                let range = range(word(pdu, 1)?, word(pdu, 3)?, 123)?;
+                let values = pdu.get(6..).ok_or(ILLEGAL_DATA_VALUE)?;
+                if usize::from(pdu[5]) != range.len() * 2 || values.len() != usize::from(pdu[5]) {
+                    return Err(ILLEGAL_DATA_VALUE);
+                }
+                for (i, index) in range.enumerate() {
+                    self.holding_registers[index] = word(values, i * 2)?;
+                }
+                Ok(pdu[..5].to_vec())
+            },
+            0x11 => {
+                let mut data = self.server_id.server_id.clone();
+                data.push(if self.server_id.run_indicator { 0xFF } else { 0x00 });
+                data.extend(self.server_id.additional_data.as_bytes());
+                data.truncate(251);
+                let mut response = vec![function, data.len() as u8];
+                response.extend(data);
+                Ok(response)
+            },
+            0x14 => self.read_file_record(pdu),
+            0x15 => self.write_file_record(pdu),
+            0x16 => {
+                let address = usize::from(word(pdu, 1)?);
+                let (and_mask, or_mask) = (word(pdu, 3)?, word(pdu, 5)?);
+                let current = self.holding_registers[address];
+                self.holding_registers[address] = (current & and_mask) | (or_mask & !and_mask);
+                Ok(pdu[..7].to_vec())
+            },
+            0x17 => {
+                let read = range(word(pdu, 1)?, word(pdu, 3)?, 125)?;
+                let write = range(word(pdu, 5)?, word(pdu, 7)?, 121)?;
+                let values = pdu.get(10..).ok_or(ILLEGAL_DATA_VALUE)?;
+                if usize::from(pdu[9]) != write.len() * 2 || values.len() != usize::from(pdu[9]) {
+                    return Err(ILLEGAL_DATA_VALUE);
+                }
+                // The write happens before the read.
+                for (i, index) in write.enumerate() {
+                    self.holding_registers[index] = word(values, i * 2)?;
+                }
+                Ok(register_response(function, &self.holding_registers[read]))
+            },
+            0x18 => {
+                let queue = self.fifos.get(&word(pdu, 1)?).ok_or(ILLEGAL_DATA_ADDRESS)?;
+                let mut response = vec![function];
+                response.extend((queue.len() as u16 * 2 + 2).to_be_bytes());
+                response.extend((queue.len() as u16).to_be_bytes());
+                response.extend(queue.iter().flat_map(|x| x.to_be_bytes()));
+                Ok(response)
+            },
+            0x2B => match pdu.get(1) {
+                Some(0x0E) => self.read_device_identification(pdu),
+                Some(_) => Err(ILLEGAL_FUNCTION),
+                None => Err(ILLEGAL_DATA_VALUE),
+            },
+            _ => Err(ILLEGAL_FUNCTION),
+        }
+    }
+
+    /// Only the sub-functions that return data or counters are supported.
+    fn diagnostics(&mut self, pdu: &[u8]) -> Result<Vec<u8>, u8> {
+        let sub_function = word(pdu, 1)?;
+        let value = match sub_function {
+            0x00 => return Ok(pdu.to_vec()),
+            0x0A => {
+                self.counters = Counters::default();
+                0
+            },
+            0x0B => self.counters.bus_messages,
+            0x0D => self.counters.bus_exception_errors,
+            0x0E => self.counters.server_messages,
+            0x02 | 0x0C | 0x0F..=0x12 => 0,
+            _ => return Err(ILLEGAL_FUNCTION),
+        };
+        let mut response = pdu[..3].to_vec();
+        response.extend(value.to_be_bytes());
+        Ok(response)
+    }
+
+    /// Each sub-request is a reference type, file number, record number and record length.
+    fn read_file_record(&self, pdu: &[u8]) -> Result<Vec<u8>, u8> {
+        let byte_count = usize::from(*pdu.get(1).ok_or(ILLEGAL_DATA_VALUE)?);
+        let requests = pdu.get(2..2 + byte_count).ok_or(ILLEGAL_DATA_VALUE)?;
+        if byte_count < 7 || byte_count % 7 != 0 {
+            return Err(ILLEGAL_DATA_VALUE);
+        }
+
+        let mut data: Vec<u8> = vec![];
+        for request in requests.chunks(7) {
+            if request[0] != 6 {
+                return Err(ILLEGAL_DATA_ADDRESS);
+            }
+            let file = self.files.get(&word(request, 1)?).ok_or(ILLEGAL_DATA_ADDRESS)?;
+            let record = usize::from(word(request, 3)?);
+            let length = usize::from(word(request, 5)?);
+            let records = file.get(record..record + length).ok_or(ILLEGAL_DATA_ADDRESS)?;
+            data.push((length * 2 + 1) as u8);
+            data.push(6);
+            data.extend(records.iter().flat_map(|x| x.to_be_bytes()));
+        }
+        if data.len() > 0xF5 {
+            return Err(ILLEGAL_DATA_VALUE);
+        }
+        let mut response = vec![0x14, data.len() as u8];
+        response.extend(data);
+        Ok(response)
+    }
+
+    /// Each sub-request is a reference type, file number, record number, record length and the records.
+    /// Files that don't exist yet are created.
+    fn write_file_record(&mut self, pdu: &[u8]) -> Result<Vec<u8>, u8> {
+        let byte_count = usize::from(*pdu.get(1).ok_or(ILLEGAL_DATA_VALUE)?);
+        let mut requests = pdu.get(2..2 + byte_count).ok_or(ILLEGAL_DATA_VALUE)?;
+        while !requests.is_empty() {
+            if requests.len() < 7 {
+                return Err(ILLEGAL_DATA_VALUE);
+            }
+            if requests[0] != 6 {
+                return Err(ILLEGAL_DATA_ADDRESS);
+            }
+            let record = usize::from(word(requests, 3)?);
+            let length = usize::from(word(requests, 5)?);
+            let values = requests.get(7..7 + length * 2).ok_or(ILLEGAL_DATA_VALUE)?;
+            if record + length > FILE_RECORDS {
+                return Err(ILLEGAL_DATA_ADDRESS);
+            }
+            let file = self.files.entry(word(requests, 1)?).or_insert_with(|| vec![0; FILE_RECORDS]);
+            for i in 0..length {
+                file[record + i] = word(values, i * 2)?;
+            }
+            requests = &requests[7 + length * 2..];
+        }
+        Ok(pdu[..2 + byte_count].to_vec())
+    }
+
+    /// Objects are streamed in as many responses as it takes, starting over from the first object of the category
+    /// if the requested one doesn't exist.
+    fn read_device_identification(&self, pdu: &[u8]) -> Result<Vec<u8>, u8> {
+        let code = *pdu.get(2).ok_or(ILLEGAL_DATA_VALUE)?;
+        let object_id = *pdu.get(3).ok_or(ILLEGAL_DATA_VALUE)?;
+        let category = match code {
+            0x01 => 0x00..=0x02,
+            0x02 => 0x03..=0x7F,
+            0x03 => 0x80..=0xFF,
+            0x04 => {
+                let value = self.device_identification.get(&object_id).ok_or(ILLEGAL_DATA_ADDRESS)?;
+                return Ok(device_identification_response(code, self.conformity_level(), 0, &[(object_id, value)]));
+            },
+            _ => return Err(ILLEGAL_DATA_VALUE),
+        };
+
+        let start = if self.device_identification.contains_key(&object_id) && category.contains(&object_id) {
+            object_id
+        } else {
+            *category.start()
+        };
+        let mut objects: Vec<(u8, &String)> = vec![];
+        let mut length = 7;
+        let mut next_object_id = 0;
+        for (&id, value) in self.device_identification.range(start..=*category.end()) {
+            let value_length = value.len().min(245);
+            if length + 2 + value_length > 253 {
+                next_object_id = id;
+                break;
+            }
+            length += 2 + value_length;
+            objects.push((id, value));
+        }
+        Ok(device_identification_response(code, self.conformity_level(), next_object_id, &objects))
+    }
+
+    /// Basic, regular or extended identification, depending on the objects defined, always with individual access.
+    fn conformity_level(&self) -> u8 {
+        match self.device_identification.keys().next_back() {
+            Some(0x80..=0xFF) => 0x83,
+            Some(0x03..=0x7F) => 0x82,
+            _ => 0x81,
+        }
+    }
+}
+
+fn register_response(function: u8, registers: &[u16]) -> Vec<u8> {
+    let mut response = vec![function, (registers.len() * 2) as u8];
+    response.extend(registers.iter().flat_map(|x| x.to_be_bytes()));
+    response
+}
+
+fn device_identification_response(code: u8, conformity_level: u8, next_object_id: u8, objects: &[(u8, &String)]) -> Vec<u8> {
+    let more_follows = if next_object_id != 0 { 0xFF } else { 0x00 };
+    let mut response = vec![0x2B, 0x0E, code, conformity_level, more_follows, next_object_id, objects.len() as u8];
+    for (id, value) in objects {
+        let value = &value.as_bytes()[..value.len().min(245)];
+        response.push(*id);
+        response.push(value.len() as u8);
+        response.extend_from_slice(value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device() -> Device {
+        Device::new(Definition {
+            holding_registers: BTreeMap::from([(100, vec![0x1234, 0x5678])]),
+            files: BTreeMap::from([(1, vec![0x0102, 0x0304])]),
+            fifos: BTreeMap::from([(5, vec![1, 2, 3]), (6, (0..40).collect())]),
+            ..Definition::default()
+        })
+    }
+
+    #[test]
+    fn reads_holding_registers() {
+        assert_eq!(device().handle(&[0x03, 0x00, 0x64, 0x00, 0x02]), [0x03, 0x04, 0x12, 0x34, 0x56, 0x78]);
+    }
+
+    #[test]
+    fn rejects_unknown_functions_and_short_requests() {
+        assert_eq!(device().handle(&[0x42]), [0xC2, ILLEGAL_FUNCTION]);
+        assert_eq!(device().handle(&[0x03, 0x00, 0x64]), [0x83, ILLEGAL_DATA_VALUE]);
+        assert!(device().handle(&[]).is_empty());
+    }
+
+    #[test]
+    fn write_multiple_coils_checks_quantity_and_byte_count() {
+        let mut device = device();
+        assert_eq!(device.handle(&[0x0F, 0x00, 0x00, 0x00, 0x0A, 0x02, 0xFF, 0x01]), [0x0F, 0x00, 0x00, 0x00, 0x0A]);
+        assert_eq!(&device.coils[..11], [true, true, true, true, true, true, true, true, true, false, false]);
+        // 1969 coils is one more than fits in a request.
+        assert_eq!(device.handle(&[0x0F, 0x00, 0x00, 0x07, 0xB1, 0x01, 0xFF]), [0x8F, ILLEGAL_DATA_VALUE]);
+        assert_eq!(device.handle(&[0x0F, 0x00, 0x00, 0x00, 0x0A, 0x01, 0xFF]), [0x8F, ILLEGAL_DATA_VALUE]);
+        assert_eq!(device.handle(&[0x0F, 0xFF, 0xFF, 0x00, 0x02, 0x01, 0x03]), [0x8F, ILLEGAL_DATA_ADDRESS]);
+    }
+
+    #[test]
+    fn write_multiple_registers_checks_quantity_and_byte_count() {
+        let mut device = device();
+        assert_eq!(device.handle(&[0x10, 0x00, 0x01, 0x00, 0x01, 0x02, 0xAB, 0xCD]), [0x10, 0x00, 0x01, 0x00, 0x01]);
+        assert_eq!(device.holding_registers[1], 0xABCD);
+        let mut too_many = vec![0x10, 0x00, 0x00, 0x00, 0x7C, 0xF8];
+        too_many.extend([0; 0xF8]);
+        assert_eq!(device.handle(&too_many), [0x90, ILLEGAL_DATA_VALUE]);
+        assert_eq!(device.handle(&[0x10, 0x00, 0x01, 0x00, 0x02, 0x02, 0xAB, 0xCD]), [0x90, ILLEGAL_DATA_VALUE]);
+        assert_eq!(device.handle(&[0x10, 0xFF, 0xFF, 0x00, 0x02, 0x04, 0x00, 0x01, 0x00, 0x02]), [0x90, ILLEGAL_DATA_ADDRESS]);
+    }
+
+    #[test]
+    fn mask_write_register_combines_masks() {
+        let mut device = device();
+        // The spec's example: 0x12 with AND mask 0xF2 and OR mask 0x25 gives 0x17.
+        device.holding_registers[4] = 0x12;
+        assert_eq!(device.handle(&[0x16, 0x00, 0x04, 0x00, 0xF2, 0x00, 0x25]), [0x16, 0x00, 0x04, 0x00, 0xF2, 0x00, 0x25]);
+        assert_eq!(device.holding_registers[4], 0x17);
+        assert_eq!(device.handle(&[0x16, 0x00, 0x04, 0x00, 0xF2, 0x00]), [0x96, ILLEGAL_DATA_VALUE]);
+    }
+
+    #[test]
+    fn read_write_multiple_registers_writes_before_reading() {
+        let mut device = device();
+        let response = device.handle(&[0x17, 0x00, 0x64, 0x00, 0x02, 0x00, 0x65, 0x00, 0x01, 0x02, 0x00, 0x07]);
+        assert_eq!(response, [0x17, 0x04, 0x12, 0x34, 0x00, 0x07]);
+        // 126 registers is one more than fits in the response, 122 one more than fits in the request.
+        assert_eq!(device.handle(&[0x17, 0x00, 0x00, 0x00, 0x7E, 0x00, 0x00, 0x00, 0x01, 0x02, 0x00, 0x00]), [0x97, ILLEGAL_DATA_VALUE]);
+        let mut too_many = vec![0x17, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x7A, 0xF4];
+        too_many.extend([0; 0xF4]);
+        assert_eq!(device.handle(&too_many), [0x97, ILLEGAL_DATA_VALUE]);
+        assert_eq!(device.handle(&[0x17, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x00, 0x00]), [0x97, ILLEGAL_DATA_VALUE]);
+    }
+
+    #[test]
+    fn reads_and_writes_file_records() {
+        let mut device = device();
+        assert_eq!(device.handle(&[0x14, 0x07, 0x06, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02]), [0x14, 0x06, 0x05, 0x06, 0x01, 0x02, 0x03, 0x04]);
+        // Writing to a file that doesn't exist yet creates it.
+        let write = [0x15, 0x09, 0x06, 0x00, 0x02, 0x00, 0x03, 0x00, 0x01, 0xBE, 0xEF];
+        assert_eq!(device.handle(&write), write);
+        assert_eq!(device.handle(&[0x14, 0x07, 0x06, 0x00, 0x02, 0x00, 0x03, 0x00, 0x01]), [0x14, 0x04, 0x03, 0x06, 0xBE, 0xEF]);
+    }
+
+    #[test]
+    fn rejects_invalid_file_record_requests() {
+        let mut device = device();
+        // Missing file, wrong reference type, and a record past the end of the file.
+        assert_eq!(device.handle(&[0x14, 0x07, 0x06, 0x00, 0x09, 0x00, 0x00, 0x00, 0x01]), [0x94, ILLEGAL_DATA_ADDRESS]);
+        assert_eq!(device.handle(&[0x14, 0x07, 0x05, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01]), [0x94, ILLEGAL_DATA_ADDRESS]);
+        assert_eq!(device.handle(&[0x14, 0x07, 0x06, 0x00, 0x01, 0x27, 0x0F, 0x00, 0x02]), [0x94, ILLEGAL_DATA_ADDRESS]);
+        // A byte count that isn't a whole number of sub-requests, and a response that wouldn't fit in a PDU.
+        assert_eq!(device.handle(&[0x14, 0x06, 0x06, 0x00, 0x01, 0x00, 0x00, 0x00]), [0x94, ILLEGAL_DATA_VALUE]);
+        assert_eq!(device.handle(&[0x14, 0x07, 0x06, 0x00, 0x01, 0x00, 0x00, 0x00, 0x7B]), [0x94, ILLEGAL_DATA_VALUE]);
+        // Writes with fewer values than records, and past the last record.
+        assert_eq!(device.handle(&[0x15, 0x09, 0x06, 0x00, 0x01, 0x27, 0x0F, 0x00, 0x02, 0x00, 0x00]), [0x95, ILLEGAL_DATA_VALUE]);
+        assert_eq!(device.handle(&[0x15, 0x09, 0x06, 0x00, 0x01, 0x27, 0x10, 0x00, 0x01, 0x00, 0x00]), [0x95, ILLEGAL_DATA_ADDRESS]);
+    }
+
+    #[test]
+    fn reads_fifo_queues() {
+        let mut device = device();
+        assert_eq!(device.handle(&[0x18, 0x00, 0x05]), [0x18, 0x00, 0x08, 0x00, 0x03, 0x00, 0x01, 0x00, 0x02, 0x00, 0x03]);
+        assert_eq!(device.handle(&[0x18, 0x00, 0x07]), [0x98, ILLEGAL_DATA_ADDRESS]);
+        // Queues longer than 31 registers are cut short when the device is created.
+        assert_eq!(device.handle(&[0x18, 0x00, 0x06])[1..5], [0x00, 0x40, 0x00, 0x1F]);
+    }
+
+    #[test]
+    fn streams_device_identification() {
+        let mut device = Device::new(Definition {
+            device_identification: BTreeMap::from([
+                (0x00, "a".repeat(100)),
+                (0x01, "b".repeat(100)),
+                (0x02, "c".repeat(100)),
+                (0x80, "private".to_string()),
+            ]),
+            ..Definition::default()
+        });
+        // Only two of the basic objects fit, so the response says where to carry on.
+        let first = device.handle(&[0x2B, 0x0E, 0x01, 0x00]);
+        assert_eq!(first[..7], [0x2B, 0x0E, 0x01, 0x83, 0xFF, 0x02, 0x02]);
+        let rest = device.handle(&[0x2B, 0x0E, 0x01, 0x02]);
+        assert_eq!(rest[..9], [0x2B, 0x0E, 0x01, 0x83, 0x00, 0x00, 0x01, 0x02, 100]);
+        // An object outside the category starts over from the category's first object.
+        assert_eq!(device.handle(&[0x2B, 0x0E, 0x01, 0x80])[..8], first[..8]);
+        let individual = device.handle(&[0x2B, 0x0E, 0x04, 0x80]);
+        assert_eq!(individual, [[0x2B, 0x0E, 0x04, 0x83, 0x00, 0x00, 0x01, 0x80, 0x07].as_slice(), b"private"].concat());
+        assert_eq!(device.handle(&[0x2B, 0x0E, 0x04, 0x05]), [0xAB, ILLEGAL_DATA_ADDRESS]);
+        assert_eq!(device.handle(&[0x2B, 0x0E, 0x05, 0x00]), [0xAB, ILLEGAL_DATA_VALUE]);
+        assert_eq!(device.handle(&[0x2B, 0x0D]), [0xAB, ILLEGAL_FUNCTION]);
+    }
+}
@@ -0,0 +1,162 @@
+use std::fmt;
+use std::fs::File;
+use std::time::Duration;
+use anyhow::{Context, Error};
+use serde::Deserialize;
+
+use super::generator::{duration, Rng};
+
+fn always() -> f64 {
+    1.0
+}
+
+/// What a fault does to the exchange.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FaultKind {
+    /// Wait before answering
+    Delay {
+        #[serde(deserialize_with = "duration")]
+        duration: Duration,
+    },
+    /// Carry out the request but never answer
+    Drop,
+    /// Answer with an exception instead of carrying out the request
+    Exception { code: u8 },
+    /// Send an RTU frame with a CRC that doesn't match
+    BadCrc,
+    /// Leave bytes off the end of the frame
+    Truncate { bytes: usize },
+    /// Pad the frame with extra bytes
+    Oversize { bytes: usize },
+    /// Answer with a different transaction id than the request's on TCP
+    WrongTransactionId,
+}
+
+/// A fault, and which requests it applies to. Address ranges only match functions that carry a starting address.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Fault {
+    #[serde(flatten)]
+    pub kind: FaultKind,
+    pub unit_id: Option<u8>,
+    pub function: Option<u8>,
+    pub first: Option<u16>,
+    pub last: Option<u16>,
+    /// Chance of the fault applying to a matching request, between 0 and 1
+    #[serde(default = "always")]
+    pub probability: f64,
+}
+
+/// The starting address of a request, for the functions that have one.
+fn request_address(pdu: &[u8]) -> Option<u16> {
+    match pdu.first()? {
+        0x01..=0x06 | 0x0F | 0x10 | 0x16 | 0x17 => pdu.get(1..3).map(|b| u16::from_be_bytes([b[0], b[1]])),
+        _ => None,
+    }
+}
+
+impl Fault {
+    fn matches(&self, unit_id: u8, pdu: &[u8]) -> bool {
+        if self.unit_id.is_some_and(|id| id != unit_id) || self.function.is_some_and(|f| pdu.first() != Some(&f)) {
+            return false;
+        }
+        if self.first.is_none() && self.last.is_none() {
+            return true;
+        }
+        match request_address(pdu) {
+            Some(address) => self.first.is_none_or(|first| address >= first) && self.last.is_none_or(|last| address <= last),
+            None => false,
+        }
+    }
+}
+
+/// Everything the faults that apply to one request do to it.
+#[derive(Debug, Default)]
+pub struct Effects {
+    pub delay: Duration,
+    pub drop: bool,
+    pub exception: Option<u8>,
+    pub bad_crc: bool,
+    pub truncate: usize,
+    pub oversize: usize,
+    pub wrong_transaction_id: bool,
+}
+
+impl Effects {
+    pub fn is_empty(&self) -> bool {
+        self.delay.is_zero() && !self.drop && self.exception.is_none() && !self.bad_crc
+            && self.truncate == 0 && self.oversize == 0 && !self.wrong_transaction_id
+    }
+
+    /// Damage an encoded frame. A bad CRC is only meaningful on RTU, so it is left to the caller.
+    pub fn mangle(&self, frame: &mut Vec<u8>) {
+        frame.truncate(frame.len().saturating_sub(self.truncate));
+        frame.extend(std::iter::repeat_n(0u8, self.oversize));
+    }
+}
+
+impl fmt::Display for Effects {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut effects: Vec<String> = vec![];
+        if !self.delay.is_zero() {
+            effects.push(format!("delay {}", humantime::format_duration(self.delay)));
+        }
+        if self.drop {
+            effects.push("drop".to_string());
+        }
+        if let Some(code) = self.exception {
+            effects.push(format!("exception {:#04x}", code));
+        }
+        if self.bad_crc {
+            effects.push("bad crc".to_string());
+        }
+        if self.truncate > 0 {
+            effects.push(format!("truncate {} bytes", self.truncate));
+        }
+        if self.oversize > 0 {
+            effects.push(format!("oversize {} bytes", self.oversize));
+        }
+        if self.wrong_transaction_id {
+            effects.push("wrong transaction id".to_string());
+        }
+        write!(f, "{}", effects.join(", "))
+    }
+}
+
+/// A list of faults to inject, read from a JSON file.
+pub struct FaultProfile {
+    faults: Vec<Fault>,
+    rng: Rng,
+}
+
+impl FaultProfile {
+    pub fn load(path: &str) -> Result<FaultProfile, Error> {
+        let file = File::open(path).with_context(|| format!("failed to open '{}'", path))?;
+        let faults = serde_json::from_reader(file).with_context(|| format!("failed to parse '{}'", path))?;
+        Ok(FaultProfile { faults, rng: Rng::new() })
+    }
+
+    pub fn none() -> FaultProfile {
+        FaultProfile { faults: vec![], rng: Rng::new() }
+    }
+
+    /// Roll for every fault that matches a request, combining those that apply. Delays add up, and the first exception wins.
+    pub fn effects(&mut self, unit_id: u8, pdu: &[u8]) -> Effects {
+        let mut effects = Effects::default();
+        for fault in &self.faults {
+            if !fault.matches(unit_id, pdu) || self.rng.next_f64() >= fault.probability {
+                continue;
+            }
+            match fault.kind {
+                FaultKind::Delay { duration } => effects.delay += duration,
+                FaultKind::Drop => effects.drop = true,
+                FaultKind::Exception { code } => { effects.exception.get_or_insert(code); },
+                FaultKind::BadCrc => effects.bad_crc = true,
+                FaultKind::Truncate { bytes } => effects.truncate += bytes,
+                FaultKind::Oversize { bytes } => effects.oversize += bytes,
+                FaultKind::WrongTransactionId => effects.wrong_transaction_id = true,
+            }
+        }
+        effects
+    }
+}
@@ -0,0 +1,160 @@
+use std::f64::consts::PI;
+use std::fs;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use anyhow::{anyhow, Context, Error};
+use serde::{Deserialize, Deserializer};
+
+pub fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    humantime::parse_duration(&s).map_err(serde::de::Error::custom)
+}
+
+fn default_interval() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_step() -> f64 {
+    1.0
+}
+
+fn default_max() -> f64 {
+    f64::from(u16::MAX)
+}
+
+/// How the values of an address are produced.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Generator {
+    /// Swing between min and max once per period
+    Sine {
+        min: f64,
+        max: f64,
+        #[serde(deserialize_with = "duration")]
+        period: Duration,
+    },
+    /// Climb from min to max over the period, then start over
+    Ramp {
+        min: f64,
+        max: f64,
+        #[serde(deserialize_with = "duration")]
+        period: Duration,
+    },
+    /// Move up or down by at most step on every update, staying between min and max
+    RandomWalk {
+        #[serde(default)]
+        min: f64,
+        #[serde(default = "default_max")]
+        max: f64,
+        #[serde(default = "default_step")]
+        step: f64,
+    },
+    /// Add step on every update, wrapping around at the end of the register
+    Counter {
+        #[serde(default)]
+        start: u16,
+        #[serde(default = "default_step")]
+        step: f64,
+    },
+    /// Play back a column of a CSV file, one row per update, starting over at the end. Rows that aren't numbers are skipped
+    Replay {
+        file: String,
+        #[serde(default)]
+        column: usize,
+    },
+}
+
+/// A generator and how often it updates its address.
+#[derive(Clone, Debug, Deserialize)]
+pub struct GeneratorDefinition {
+    #[serde(flatten)]
+    pub generator: Generator,
+    #[serde(default = "default_interval", deserialize_with = "duration")]
+    pub interval: Duration,
+}
+
+fn clamp(value: f64) -> u16 {
+    value.round().clamp(0.0, f64::from(u16::MAX)) as u16
+}
+
+/// A xorshift64 generator, which is plenty for simulated noise.
+pub struct Rng(u64);
+
+impl Rng {
+    /// Seeded from the clock, so every run differs.
+    pub fn new() -> Rng {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+        Rng(nanos | 1)
+    }
+
+    /// A number in [0, 1).
+    pub fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// The running state of a generator.
+pub struct Source {
+    generator: Generator,
+    started: Instant,
+    /// Where a random walk has got to
+    value: f64,
+    rng: Rng,
+    replay: Vec<u16>,
+    updates: usize,
+}
+
+impl Source {
+    pub fn new(generator: Generator) -> Result<Source, Error> {
+        let replay = match &generator {
+            Generator::Replay { file, column } => {
+                let contents = fs::read_to_string(file).with_context(|| format!("failed to read '{}'", file))?;
+                let values: Vec<u16> = contents.lines()
+                    .filter_map(|line| line.split(',').nth(*column))
+                    .filter_map(|field| field.trim().parse::<f64>().ok())
+                    .map(clamp)
+                    .collect();
+                if values.is_empty() {
+                    return Err(anyhow!("'{}' has no numbers in column {}", file, column));
+                }
+                values
+            },
+            _ => vec![],
+        };
+        // Random walks start halfway between their bounds.
+        let value = match generator {
+            Generator::RandomWalk { min, max, .. } => (min + max) / 2.0,
+            _ => 0.0,
+        };
+        Ok(Source { generator, started: Instant::now(), value, rng: Rng::new(), replay, updates: 0 })
+    }
+
+    /// The value for the current update.
+    pub fn next_value(&mut self) -> u16 {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let value = match self.generator {
+            Generator::Sine { min, max, period } => {
+                let phase = 2.0 * PI * elapsed / period.as_secs_f64();
+                clamp(min + (max - min) * (1.0 + phase.sin()) / 2.0)
+            },
+            Generator::Ramp { min, max, period } => {
+                let fraction = (elapsed / period.as_secs_f64()).fract();
+                clamp(min + (max - min) * fraction)
+            },
+            Generator::RandomWalk { min, max, step } => {
+                let delta = (self.rng.next_f64() * 2.0 - 1.0) * step;
+                self.value = (self.value + delta).clamp(min, max);
+                clamp(self.value)
+            },
+            Generator::Counter { start, step } => {
+                let value = (f64::from(start) + step * self.updates as f64).rem_euclid(65536.0);
+                value as u16
+            },
+            Generator::Replay { .. } => self.replay[self.updates % self.replay.len()],
+        };
+        self.updates += 1;
+        value
+    }
+}
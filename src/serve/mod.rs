@@ -0,0 +1,179 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use anyhow::{anyhow, Context, Error};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::frame::{rtu, tcp};
+use crate::uri::{ModbusUri, Proto};
+
+pub mod args;
+mod device;
+mod fault;
+mod generator;
+mod rule;
+
+use device::{Definition, Device};
+use fault::{Effects, FaultProfile};
+use generator::Source;
+use rule::Reference;
+
+/// Serial adapters and ptys deliver bytes in bursts, so wait a little longer than the spec's gap before ending a frame.
+const MIN_FRAME_GAP: Duration = Duration::from_millis(5);
+
+/// Something that answers request PDUs: a simulated device, or a recorded session.
+pub trait Handler: Send {
+    fn handle(&mut self, unit_id: u8, request: &[u8]) -> Vec<u8>;
+}
+
+impl Handler for Device {
+    fn handle(&mut self, _unit_id: u8, request: &[u8]) -> Vec<u8> {
+        Device::handle(self, request)
+    }
+}
+
+pub type SharedDevice = Arc<Mutex<dyn Handler>>;
+type SharedFaults = Arc<Mutex<FaultProfile>>;
+
+/// Work out what to answer a request with, applying any faults. Returns None if no answer should be sent.
+async fn respond(device: &SharedDevice, faults: &SharedFaults, unit_id: u8, pdu: &[u8]) -> (Effects, Option<Vec<u8>>) {
+    let effects = faults.lock().unwrap().effects(unit_id, pdu);
+    if !effects.is_empty() {
+        eprintln!("injecting {} into function {:#04x} for unit {}", effects, pdu.first().unwrap_or(&0), unit_id);
+    }
+    if !effects.delay.is_zero() {
+        tokio::time::sleep(effects.delay).await;
+    }
+    let response = match (effects.exception, pdu.first()) {
+        (Some(code), Some(&function)) => vec![function | 0x80, code],
+        _ => device.lock().unwrap().handle(unit_id, pdu),
+    };
+    let response = if effects.drop { None } else { Some(response) };
+    (effects, response)
+}
+
+fn answers(unit_ids: &[u8], unit_id: u8) -> bool {
+    unit_ids.is_empty() || unit_ids.contains(&unit_id)
+}
+
+async fn serve_connection(mut stream: TcpStream, device: SharedDevice, faults: SharedFaults, unit_ids: Arc<Vec<u8>>) -> Result<(), Error> {
+    while let Some((header, pdu)) = tcp::read_frame(&mut stream).await? {
+        if !answers(&unit_ids, header.unit_id) {
+            continue;
+        }
+        let (effects, response) = respond(&device, &faults, header.unit_id, &pdu).await;
+        let response = match response {
+            Some(response) => response,
+            None => continue,
+        };
+        let transaction_id = if effects.wrong_transaction_id {
+            header.transaction_id.wrapping_add(1)
+        } else {
+            header.transaction_id
+        };
+        let mut frame = tcp::encode(transaction_id, header.unit_id, &response);
+        effects.mangle(&mut frame);
+        stream.write_all(&frame).await?;
+    }
+    Ok(())
+}
+
+async fn serve_tcp(uri: ModbusUri, device: SharedDevice, faults: SharedFaults, unit_ids: Vec<u8>) -> Result<(), Error> {
+    let port = u16::try_from(uri.port).map_err(|_| anyhow!("invalid port {}", uri.port))?;
+    let listener = TcpListener::bind((uri.host.as_str(), port))
+        .await
+        .with_context(|| format!("could not listen on `{}`", uri))?;
+    eprintln!("listening on {}", listener.local_addr()?);
+
+    let unit_ids = Arc::new(unit_ids);
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        eprintln!("{} connected", peer);
+        let device = device.clone();
+        let faults = faults.clone();
+        let unit_ids = unit_ids.clone();
+        tokio::spawn(async move {
+            match serve_connection(stream, device, faults, unit_ids).await {
+                Ok(()) => eprintln!("{} disconnected", peer),
+                Err(e) => eprintln!("{} disconnected: {:#}", peer, e),
+            }
+        });
+    }
+}
+
+async fn serve_rtu(uri: ModbusUri, device: SharedDevice, faults: SharedFaults, unit_ids: Vec<u8>) -> Result<(), Error> {
+    let mut port = tokio_serial::SerialStream::open(&tokio_serial::new(uri.host.as_str(), uri.port))
+        .with_context(|| format!("could not open `{}`", uri))?;
+    eprintln!("serving on {}", uri);
+
+    let gap = rtu::frame_gap(uri.port).max(MIN_FRAME_GAP);
+    loop {
+        let frame = rtu::read_frame(&mut port, None, gap).await?;
+        let (unit_id, pdu) = match rtu::decode(&frame) {
+            Some(decoded) => decoded,
+            None => {
+                eprintln!("dropped invalid frame of {} bytes", frame.len());
+                continue;
+            },
+        };
+        if unit_id != 0 && !answers(&unit_ids, unit_id) {
+            continue;
+        }
+        let (effects, response) = respond(&device, &faults, unit_id, pdu).await;
+        // Broadcasts are carried out but never answered.
+        let response = match response {
+            Some(response) if unit_id != 0 => response,
+            _ => continue,
+        };
+        let mut frame = rtu::encode(unit_id, &response);
+        if effects.bad_crc {
+            let last = frame.len() - 1;
+            frame[last] ^= 0xFF;
+        }
+        effects.mangle(&mut frame);
+        port.write_all(&frame).await?;
+    }
+}
+
+/// Update an address from a generator until the server stops.
+async fn generate(device: Arc<Mutex<Device>>, reference: Reference, interval: Duration, mut source: Source) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        let value = source.next_value();
+        device.lock().unwrap().update(reference, value);
+    }
+}
+
+/// Simulate a device on the given address until interrupted.
+pub async fn serve_action(uri: ModbusUri, args: args::ServeArgs) -> Result<(), Error> {
+    let definition = match &args.definition {
+        Some(path) => Definition::load(path)?,
+        None => Definition::default(),
+    };
+    let sources = definition.generators.iter()
+        .map(|(&reference, generator)| Ok((reference, generator.interval, Source::new(generator.generator.clone())?)))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let faults = match &args.faults {
+        Some(path) => FaultProfile::load(path)?,
+        None => FaultProfile::none(),
+    };
+    let faults = Arc::new(Mutex::new(faults));
+    let device = Arc::new(Mutex::new(Device::new(definition)));
+    for (reference, interval, source) in sources {
+        tokio::spawn(generate(device.clone(), reference, interval, source));
+    }
+    match uri.proto {
+        Proto::Tcp => serve_tcp(uri, device, faults, args.unit_ids).await,
+        Proto::Rtu => serve_rtu(uri, device, faults, args.unit_ids).await,
+    }
+}
+
+/// Answer every unit id on the given address with another handler, without faults, until interrupted.
+pub async fn serve_with(uri: ModbusUri, handler: SharedDevice) -> Result<(), Error> {
+    let faults = Arc::new(Mutex::new(FaultProfile::none()));
+    match uri.proto {
+        Proto::Tcp => serve_tcp(uri, handler, faults, vec![]).await,
+        Proto::Rtu => serve_rtu(uri, handler, faults, vec![]).await,
+    }
+}
@@ -0,0 +1,48 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use serde::Deserialize;
+
+use crate::alarm::{self, Condition};
+use crate::client::Table;
+
+/// A single address of one of the tables, written like `hr:10` in the definition file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Reference {
+    pub table: Table,
+    pub address: u16,
+}
+
+impl TryFrom<String> for Reference {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let (table, address) = alarm::parse_reference(&s).map_err(|e| format!("invalid reference '{}': {}", s, e))?;
+        Ok(Reference { table, address })
+    }
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.table, self.address)
+    }
+}
+
+/// The condition of a rule, written the same way as alarm conditions, e.g. `co:5 == true`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(try_from = "String")]
+pub struct When(pub Condition);
+
+impl TryFrom<String> for When {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse::<Condition>().map(When)
+    }
+}
+
+/// Set addresses when a condition becomes true. Coils and discrete inputs are set true by any non-zero value.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub when: When,
+    pub set: BTreeMap<Reference, u16>,
+}
@@ -0,0 +1,18 @@
+
+use clap::Args;
+
+/// Replay a session recorded with --record, against the device given by the URI or as a server on it
+#[derive(Args, Clone, Debug)]
+pub struct ReplayArgs {
+    /// session file written by --record
+    #[clap(value_parser)]
+    pub session: String,
+
+    /// answer recorded requests with their recorded responses, listening on the URI, instead of re-issuing them
+    #[clap(long)]
+    pub serve: bool,
+
+    /// wait between requests as long as the recording did
+    #[clap(long)]
+    pub realtime: bool,
+}
@@ -0,0 +1,179 @@
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Error, Write};
+use std::time::{Instant, SystemTime};
+use anyhow::Context as _;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio_modbus::prelude::{Client, Request, Response, Slave, SlaveContext};
+
+use crate::client::{self, Context};
+use crate::frame::pdu;
+
+pub mod args;
+mod replay;
+
+pub use replay::{replay_action, serve_recording};
+
+/// One request of a recorded session. PDUs are stored as hex bytes. Exceptions are recorded as responses;
+/// other failures, like timeouts, leave the response out and record the error.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub timestamp: String,
+    pub unit_id: u8,
+    pub request: String,
+    pub response: Option<String>,
+    pub error: Option<String>,
+    pub elapsed_ms: f64,
+}
+
+/// Parse hex bytes, as written by `pdu::hex`. Whitespace between digits is ignored.
+pub fn from_hex(s: &str) -> Result<Vec<u8>, String> {
+    let digits = s.chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_digit(16).map(|d| d as u8).ok_or_else(|| format!("invalid hex '{}': '{}' is not a hex digit", s, c)))
+        .collect::<Result<Vec<u8>, String>>()?;
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("invalid hex '{}': odd number of digits", s));
+    }
+    Ok(digits.chunks(2).map(|pair| pair[0] << 4 | pair[1]).collect())
+}
+
+/// Read every entry of a recorded session.
+pub fn load(path: &str) -> Result<Vec<Entry>, anyhow::Error> {
+    let file = File::open(path).with_context(|| format!("failed to open '{}'", path))?;
+    BufReader::new(file)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.as_ref().map_or(true, |l| !l.trim().is_empty()))
+        .map(|(i, line)| {
+            let line = line.with_context(|| format!("failed to read '{}'", path))?;
+            serde_json::from_str(&line).with_context(|| format!("{}:{}: invalid entry", path, i + 1))
+        })
+        .collect()
+}
+
+/// A client that records every request and response passing through it to a JSON lines file.
+struct Recorder {
+    inner: Box<dyn Client>,
+    unit_id: u8,
+    file: File,
+}
+
+impl fmt::Debug for Recorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Recorder").field("inner", &self.inner).field("unit_id", &self.unit_id).finish()
+    }
+}
+
+impl SlaveContext for Recorder {
+    fn set_slave(&mut self, slave: Slave) {
+        self.unit_id = slave.0;
+        self.inner.set_slave(slave);
+    }
+}
+
+#[async_trait]
+impl Client for Recorder {
+    async fn call(&mut self, request: Request) -> Result<Response, Error> {
+        if request == Request::Disconnect {
+            return self.inner.call(request).await;
+        }
+        let request_pdu = pdu::encode_request(request.clone());
+        let timestamp = humantime::format_rfc3339_millis(SystemTime::now()).to_string();
+        let started = Instant::now();
+        let result = self.inner.call(request).await;
+
+        let (response, error) = match &result {
+            Ok(response) => (Some(pdu::hex(&pdu::encode_response(response.clone()))), None),
+            Err(e) => match client::exception_code(e) {
+                Some(code) => (Some(pdu::hex(&pdu::exception(request_pdu[0], code))), None),
+                None => (None, Some(e.to_string())),
+            },
+        };
+        let entry = Entry {
+            timestamp,
+            unit_id: self.unit_id,
+            request: pdu::hex(&request_pdu),
+            response,
+            error,
+            elapsed_ms: started.elapsed().as_secs_f64() * 1000.0,
+        };
+        let line = serde_json::to_string(&entry)?;
+        writeln!(self.file, "{}", line)?;
+        result
+    }
+}
+
+/// Wrap a connection so everything sent through it is appended to `path`.
+pub fn record(context: Context, unit_id: u8, path: &str) -> Result<Context, anyhow::Error> {
+    let file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)
+        .with_context(|| format!("could not open session file '{}'", path))?;
+    let recorder = Recorder { inner: context.into(), unit_id, file };
+    Ok(Context::from(Box::new(recorder) as Box<dyn Client>))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::io::ErrorKind;
+    use super::*;
+
+    /// A client that answers with canned results, in order.
+    #[derive(Debug)]
+    struct Canned(VecDeque<Result<Response, Error>>);
+
+    impl SlaveContext for Canned {
+        fn set_slave(&mut self, _: Slave) {}
+    }
+
+    #[async_trait]
+    impl Client for Canned {
+        async fn call(&mut self, _: Request) -> Result<Response, Error> {
+            self.0.pop_front().unwrap_or_else(|| Err(Error::new(ErrorKind::TimedOut, "timed out")))
+        }
+    }
+
+    #[test]
+    fn from_hex_parses_spaced_and_packed_bytes() {
+        assert_eq!(from_hex("01 03 fF"), Ok(vec![0x01, 0x03, 0xFF]));
+        assert_eq!(from_hex("0103FF"), Ok(vec![0x01, 0x03, 0xFF]));
+        assert_eq!(from_hex(""), Ok(vec![]));
+        assert_eq!(from_hex(&pdu::hex(&[0x00, 0xAB])), Ok(vec![0x00, 0xAB]));
+    }
+
+    #[test]
+    fn from_hex_rejects_invalid_digits() {
+        assert!(from_hex("013").is_err());
+        assert!(from_hex("0x").is_err());
+        assert!(from_hex("+1").is_err());
+        assert!(from_hex("aé0").is_err());
+    }
+
+    #[tokio::test]
+    async fn recorder_round_trips_through_load() {
+        let canned = Canned(VecDeque::from([
+            Ok(Response::ReadHoldingRegisters(vec![0x1234])),
+            Err(Error::other("Modbus function 3: Illegal data address")),
+        ]));
+        let path = std::env::temp_dir().join(format!("mbc-recorder-{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut client = record(Context::from(Box::new(canned) as Box<dyn Client>), 1, path).unwrap();
+        client.set_slave(Slave(2));
+        assert!(client.call(Request::ReadHoldingRegisters(100, 1)).await.is_ok());
+        assert!(client.call(Request::ReadHoldingRegisters(200, 1)).await.is_err());
+        assert!(client.call(Request::ReadHoldingRegisters(300, 1)).await.is_err());
+        let entries = load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(entries.len(), 3);
+        assert!(entries.iter().all(|entry| entry.unit_id == 2));
+        assert_eq!((entries[0].request.as_str(), entries[0].response.as_deref()), ("03 00 64 00 01", Some("03 02 12 34")));
+        // Exceptions are recorded as responses, other failures as errors.
+        assert_eq!((entries[1].response.as_deref(), entries[1].error.as_deref()), (Some("83 02"), None));
+        assert_eq!((entries[2].response.as_deref(), entries[2].error.as_deref()), (None, Some("timed out")));
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use anyhow::{anyhow, Context as _, Error};
+use tokio_modbus::prelude::{Client, Slave, SlaveContext};
+
+use crate::client::{self, Context};
+use crate::frame::pdu::{self, Exchange};
+use crate::output::Output;
+use crate::serve::{self, Handler};
+use crate::uri::ModbusUri;
+use super::{args::ReplayArgs, from_hex, load, Entry};
+
+/// Describe how a request was answered: its values, its exception, or the error if there was no answer.
+fn outcome(request: &[u8], response: Option<&[u8]>, error: Option<&str>) -> String {
+    match response {
+        Some(response) => {
+            let exchange = Exchange::decode(Some(request), Some(response));
+            if exchange.exception.is_empty() { exchange.values } else { exchange.exception }
+        },
+        None => error.unwrap_or("no response").to_string(),
+    }
+}
+
+/// Whether a replayed request was answered as recorded. Requests that went unanswered must fail the same way again.
+fn matches_recording(recorded: Option<&[u8]>, recorded_error: Option<&str>, replayed: Option<&[u8]>, error: Option<&str>) -> bool {
+    replayed == recorded && (recorded.is_some() || error == recorded_error)
+}
+
+fn parse_entry(entry: &Entry) -> Result<(Vec<u8>, Option<Vec<u8>>), Error> {
+    let request = from_hex(&entry.request).map_err(|e| anyhow!(e))?;
+    let response = entry.response.as_deref().map(from_hex).transpose().map_err(|e| anyhow!(e))?;
+    if request.is_empty() {
+        return Err(anyhow!("entry at {} has an empty request", entry.timestamp));
+    }
+    Ok((request, response))
+}
+
+/// Send every recorded request again and compare the responses with the recorded ones.
+async fn reissue(client: &mut Context, entries: Vec<Entry>, args: &ReplayArgs, output: &mut dyn Output) -> Result<(), Error> {
+    let mut columns = vec!["index".to_string(), "unit_id".to_string()];
+    columns.extend(Exchange::columns().into_iter().take(3));
+    columns.extend(["recorded".to_string(), "replayed".to_string(), "matches".to_string()]);
+    output.write_header(&columns)?;
+
+    let mut previous: Option<SystemTime> = None;
+    let mut mismatches = 0;
+    for (index, entry) in entries.iter().enumerate() {
+        let (request_pdu, recorded) = parse_entry(entry)?;
+        let request = pdu::decode_request(&request_pdu)
+            .with_context(|| format!("entry {}: could not decode request {}", index, entry.request))?;
+
+        if args.realtime {
+            let timestamp = humantime::parse_rfc3339(&entry.timestamp)
+                .with_context(|| format!("entry {}: invalid timestamp '{}'", index, entry.timestamp))?;
+            if let Some(gap) = previous.and_then(|p| timestamp.duration_since(p).ok()) {
+                tokio::time::sleep(gap).await;
+            }
+            previous = Some(timestamp);
+        }
+
+        client.set_slave(Slave(entry.unit_id));
+        let (replayed, error) = match client.call(request).await {
+            Ok(response) => (Some(pdu::encode_response(response)), None),
+            Err(e) => match client::exception_code(&e) {
+                Some(code) => (Some(pdu::exception(request_pdu[0], code)), None),
+                None => (None, Some(e.to_string())),
+            },
+        };
+        let matches = matches_recording(recorded.as_deref(), entry.error.as_deref(), replayed.as_deref(), error.as_deref());
+        if !matches {
+            mismatches += 1;
+        }
+
+        let mut row = vec![index.to_string(), entry.unit_id.to_string()];
+        row.extend(Exchange::decode(Some(&request_pdu), None).row().into_iter().take(3));
+        row.push(outcome(&request_pdu, recorded.as_deref(), entry.error.as_deref()));
+        row.push(outcome(&request_pdu, replayed.as_deref(), error.as_deref()));
+        row.push(matches.to_string());
+        output.write_rows(&[row])?;
+    }
+
+    if mismatches > 0 {
+        return Err(anyhow!("{} of {} responses differ from the recording", mismatches, entries.len()));
+    }
+    Ok(())
+}
+
+/// Answers requests with the responses recorded for them. A request recorded several times is answered with each of
+/// its responses in turn, so changing values play back.
+struct Recording {
+    /// Responses by unit id and request, ordered so the unit id fallback always picks the lowest unit.
+    responses: BTreeMap<(u8, Vec<u8>), Vec<Vec<u8>>>,
+    answered: HashMap<(u8, Vec<u8>), usize>,
+}
+
+impl Recording {
+    fn new(entries: &[Entry]) -> Result<Recording, Error> {
+        let mut responses: BTreeMap<(u8, Vec<u8>), Vec<Vec<u8>>> = BTreeMap::new();
+        for entry in entries {
+            // Requests that went unanswered are left out, so they go unanswered again.
+            if let (request, Some(response)) = parse_entry(entry)? {
+                responses.entry((entry.unit_id, request)).or_default().push(response);
+            }
+        }
+        Ok(Recording { responses, answered: HashMap::new() })
+    }
+}
+
+impl Handler for Recording {
+    fn handle(&mut self, unit_id: u8, request: &[u8]) -> Vec<u8> {
+        // Clients often address a different unit id than the recording did, so fall back to the lowest unit that
+        // recorded the request.
+        let key = Some((unit_id, request.to_vec()))
+            .filter(|key| self.responses.contains_key(key))
+            .or_else(|| self.responses.keys().find(|(_, r)| r == request).cloned());
+        match key {
+            Some(key) => {
+                let count = self.answered.entry(key.clone()).or_default();
+                let responses = &self.responses[&key];
+                let response = responses[*count % responses.len()].clone();
+                *count += 1;
+                response
+            },
+            None => {
+                eprintln!("no recorded response to {} for unit {}", pdu::hex(request), unit_id);
+                pdu::exception(request.first().copied().unwrap_or_default(), client::SERVER_DEVICE_FAILURE)
+            },
+        }
+    }
+}
+
+/// Serve a recording on the given address until interrupted.
+pub async fn serve_recording(uri: ModbusUri, args: ReplayArgs) -> Result<(), Error> {
+    let entries = load(&args.session)?;
+    let recording = Recording::new(&entries)?;
+    serve::serve_with(uri, Arc::new(Mutex::new(recording))).await
+}
+
+pub async fn replay_action(client: &mut Context, args: ReplayArgs, output: &mut dyn Output) -> Result<(), Error> {
+    let entries = load(&args.session)?;
+    reissue(client, entries, &args, output).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(unit_id: u8, request: &str, response: Option<&str>) -> Entry {
+        Entry {
+            timestamp: "2022-10-19T04:38:25.137Z".to_string(),
+            unit_id,
+            request: request.to_string(),
+            response: response.map(str::to_string),
+            error: None,
+            elapsed_ms: 1.0,
+        }
+    }
+
+    #[test]
+    fn recording_cycles_through_responses() {
+        let mut recording = Recording::new(&[
+            entry(1, "03 00 00 00 01", Some("03 02 00 01")),
+            entry(1, "03 00 00 00 01", Some("03 02 00 02")),
+        ]).unwrap();
+        let request = [0x03, 0x00, 0x00, 0x00, 0x01];
+        assert_eq!(recording.handle(1, &request), [0x03, 0x02, 0x00, 0x01]);
+        assert_eq!(recording.handle(1, &request), [0x03, 0x02, 0x00, 0x02]);
+        assert_eq!(recording.handle(1, &request), [0x03, 0x02, 0x00, 0x01]);
+    }
+
+    #[test]
+    fn recording_falls_back_to_the_lowest_unit() {
+        let mut recording = Recording::new(&[
+            entry(7, "03 00 00 00 01", Some("03 02 00 07")),
+            entry(3, "03 00 00 00 01", Some("03 02 00 03")),
+        ]).unwrap();
+        let request = [0x03, 0x00, 0x00, 0x00, 0x01];
+        assert_eq!(recording.handle(7, &request), [0x03, 0x02, 0x00, 0x07]);
+        assert_eq!(recording.handle(0xFF, &request), [0x03, 0x02, 0x00, 0x03]);
+    }
+
+    #[test]
+    fn recording_fails_requests_it_doesnt_have() {
+        // An unanswered request isn't answered on replay either.
+        let mut recording = Recording::new(&[entry(1, "03 00 00 00 01", None)]).unwrap();
+        assert_eq!(recording.handle(1, &[0x03, 0x00, 0x00, 0x00, 0x01]), [0x83, client::SERVER_DEVICE_FAILURE]);
+        assert_eq!(recording.handle(1, &[0x04, 0x00, 0x00, 0x00, 0x01]), [0x84, client::SERVER_DEVICE_FAILURE]);
+    }
+
+    #[test]
+    fn recording_rejects_invalid_requests() {
+        assert!(Recording::new(&[entry(1, "", Some("03 02 00 01"))]).is_err());
+        assert!(Recording::new(&[entry(1, "0G", Some("03 02 00 01"))]).is_err());
+    }
+
+    #[test]
+    fn replay_matches_responses_and_errors() {
+        let response: &[u8] = &[0x03, 0x02, 0x00, 0x01];
+        assert!(matches_recording(Some(response), None, Some(response), None));
+        assert!(!matches_recording(Some(response), None, Some(&[0x03, 0x02, 0x00, 0x02]), None));
+        assert!(!matches_recording(Some(response), None, None, Some("timed out")));
+        // Without a recorded response, the request has to fail with the same error.
+        assert!(matches_recording(None, Some("timed out"), None, Some("timed out")));
+        assert!(!matches_recording(None, Some("timed out"), None, Some("broken pipe")));
+        assert!(!matches_recording(None, Some("timed out"), Some(response), None));
+    }
+}
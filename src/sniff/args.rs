@@ -0,0 +1,16 @@
+use std::time::Duration;
+use clap::Args;
+
+use crate::scan::args::Framing;
+
+/// Listen to the RTU line given by the URI without transmitting, decoding the requests and responses on it
+#[derive(Args, Clone, Debug)]
+pub struct SniffArgs {
+    /// data bits, parity and stop bits of the line, e.g. 8E1
+    #[clap(long, value_parser, default_value = "8N1")]
+    pub framing: Framing,
+
+    /// how long a device may take to answer before its request is logged as unanswered
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "1s")]
+    pub timeout: Duration,
+}
@@ -0,0 +1,168 @@
+use std::time::{Duration, Instant, SystemTime};
+use anyhow::{anyhow, Context, Error};
+use tokio_serial::SerialStream;
+
+use crate::frame::pdu::{self, Exchange};
+use crate::frame::rtu;
+use crate::output::Output;
+use crate::uri::{ModbusUri, Proto};
+
+pub mod args;
+
+/// Serial adapters deliver bytes in bursts, so wait a little longer than the spec's gap before ending a frame.
+const MIN_FRAME_GAP: Duration = Duration::from_millis(5);
+
+/// A request seen on the line, waiting for its response.
+struct Pending {
+    timestamp: SystemTime,
+    started: Instant,
+    unit_id: u8,
+    pdu: Vec<u8>,
+}
+
+fn row(timestamp: SystemTime, unit_id: u8, exchange: Exchange, rtt: String) -> Vec<String> {
+    let mut row = vec![humantime::format_rfc3339_millis(timestamp).to_string(), unit_id.to_string()];
+    row.extend(exchange.row());
+    row.push(rtt);
+    row
+}
+
+fn unanswered(request: Pending) -> Vec<String> {
+    row(request.timestamp, request.unit_id, Exchange::decode(Some(&request.pdu), None), String::new())
+}
+
+/// Split a burst of bytes into CRC-checked frames. Devices that answer quicker than the frame gap, or adapters that
+/// buffer, can run frames together, so a burst that isn't one frame is split at the first prefix with a valid CRC.
+/// Where no frame starts, a byte is dropped and the search starts again from the next one; the bytes skipped that way
+/// are reported.
+fn split_frames(mut burst: &[u8]) -> Vec<(u8, Vec<u8>)> {
+    let mut frames = vec![];
+    let mut skipped: Vec<u8> = vec![];
+    while !burst.is_empty() {
+        let len = if rtu::decode(burst).is_some() { Some(burst.len()) } else { rtu::frame_len(burst) };
+        match len {
+            Some(len) => {
+                report_skipped(&mut skipped);
+                let (unit_id, pdu) = rtu::decode(&burst[..len]).unwrap_or_default();
+                frames.push((unit_id, pdu.to_vec()));
+                burst = &burst[len..];
+            },
+            None => {
+                skipped.push(burst[0]);
+                burst = &burst[1..];
+            },
+        }
+    }
+    report_skipped(&mut skipped);
+    frames
+}
+
+fn report_skipped(skipped: &mut Vec<u8>) {
+    if !skipped.is_empty() {
+        eprintln!("dropped {} bytes that aren't a valid frame: {}", skipped.len(), pdu::hex(skipped));
+        skipped.clear();
+    }
+}
+
+/// Decode the traffic on an RTU line until interrupted, writing each exchange to the output as it completes.
+/// Nothing is ever written to the port. A frame is taken as the response to the request before it when it comes from
+/// the same unit with the same function within the timeout; any other frame is a new request.
+pub async fn sniff_action(uri: ModbusUri, args: args::SniffArgs, output: &mut dyn Output) -> Result<(), Error> {
+    if !matches!(uri.proto, Proto::Rtu) {
+        return Err(anyhow!("sniffing listens on an rtu:// line, got `{}`", uri));
+    }
+    let builder = tokio_serial::new(uri.host.as_str(), uri.port)
+        .data_bits(args.framing.data_bits)
+        .parity(args.framing.parity)
+        .stop_bits(args.framing.stop_bits);
+    let mut port = SerialStream::open(&builder).with_context(|| format!("could not open `{}`", uri))?;
+    eprintln!("sniffing {} {}", uri, args.framing);
+
+    let mut columns = vec!["timestamp".to_string(), "unit_id".to_string()];
+    columns.extend(Exchange::columns());
+    columns.push("rtt_ms".to_string());
+    output.write_header(&columns)?;
+
+    let gap = rtu::frame_gap(uri.port).max(MIN_FRAME_GAP);
+    let mut pending: Option<Pending> = None;
+    loop {
+        let wait = pending.as_ref().map(|request| args.timeout.saturating_sub(request.started.elapsed()));
+        let burst = rtu::read_burst(&mut port, wait, gap).await?;
+        let received = SystemTime::now();
+        if burst.is_empty() {
+            match pending.take() {
+                Some(request) => output.write_rows(&[unanswered(request)])?,
+                None => return Ok(()),
+            }
+            continue;
+        }
+
+        for (unit_id, frame) in split_frames(&burst) {
+            let function = frame.first().map(|f| f & 0x7F);
+            let request = match pending.take() {
+                Some(request) if request.unit_id == unit_id && request.pdu.first().copied() == function
+                    && request.started.elapsed() <= args.timeout => {
+                    let rtt = format!("{:.1}", request.started.elapsed().as_secs_f64() * 1000.0);
+                    let exchange = Exchange::decode(Some(&request.pdu), Some(&frame));
+                    output.write_rows(&[row(request.timestamp, unit_id, exchange, rtt)])?;
+                    continue;
+                },
+                Some(request) => {
+                    output.write_rows(&[unanswered(request)])?;
+                    Pending { timestamp: received, started: Instant::now(), unit_id, pdu: frame }
+                },
+                None => Pending { timestamp: received, started: Instant::now(), unit_id, pdu: frame },
+            };
+            // Broadcasts are never answered.
+            if unit_id == 0 {
+                let mut exchange = Exchange::decode(Some(&request.pdu), None);
+                exchange.exception = String::new();
+                output.write_rows(&[row(request.timestamp, unit_id, exchange, String::new())])?;
+            } else {
+                pending = Some(request);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REQUEST: [u8; 8] = [0x01, 0x03, 0x00, 0x00, 0x00, 0x0A, 0xC5, 0xCD];
+
+    #[test]
+    fn keeps_a_single_frame_whole() {
+        assert_eq!(split_frames(&REQUEST), [(1, REQUEST[1..6].to_vec())]);
+    }
+
+    #[test]
+    fn splits_frames_run_together() {
+        let response = rtu::encode(1, &[0x03, 0x02, 0x00, 0x07]);
+        let burst = [REQUEST.to_vec(), response].concat();
+        assert_eq!(split_frames(&burst), [(1, REQUEST[1..6].to_vec()), (1, vec![0x03, 0x02, 0x00, 0x07])]);
+    }
+
+    #[test]
+    fn resyncs_after_noise() {
+        let response = rtu::encode(1, &[0x03, 0x02, 0x00, 0x07]);
+        let burst = [&[0xAA, 0xBB][..], &REQUEST, &[0xCC], &response].concat();
+        assert_eq!(split_frames(&burst), [(1, REQUEST[1..6].to_vec()), (1, vec![0x03, 0x02, 0x00, 0x07])]);
+    }
+
+    #[test]
+    fn keeps_frames_past_the_largest_frame_length() {
+        let response = rtu::encode(1, &[[0x03, 250].as_slice(), &[0x55; 250]].concat());
+        let burst = [REQUEST.to_vec(), response.clone(), REQUEST.to_vec()].concat();
+        assert!(burst.len() > rtu::MAX_FRAME_LEN);
+        let frames = split_frames(&burst);
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[1].1, response[1..response.len() - 2]);
+    }
+
+    #[test]
+    fn drops_noise_without_frames() {
+        assert!(split_frames(&[0x01, 0x03, 0x00]).is_empty());
+        assert!(split_frames(&[]).is_empty());
+    }
+}
@@ -1,4 +1,5 @@
 use std::fmt;
+use std::num::ParseIntError;
 use std::str::FromStr;
 use http::uri::{InvalidUri, Uri};
 
@@ -59,6 +60,7 @@ pub enum UriError {
     Scheme(InvalidScheme),
     Missing(MissingComponent),
     Uri(InvalidUri),
+    BaudRate(ParseIntError),
 }
 
 impl fmt::Display for UriError {
@@ -70,6 +72,8 @@ impl fmt::Display for UriError {
                 write!(f, "{}", uri_error),
             UriError::Missing(missing_error) =>
                 write!(f, "{}", missing_error),
+            UriError::BaudRate(baud_error) =>
+                write!(f, "invalid baud rate: {}", baud_error),
         }
     }
 }
@@ -89,7 +93,7 @@ impl From<InvalidUri> for UriError {
     }
 }
 
-fn default_port_for_proto(proto: Proto) -> u16 {
+fn default_port_for_proto(proto: Proto) -> u32 {
     match proto {
         Proto::Rtu => 9600,
         Proto::Tcp => 502,
@@ -99,8 +103,25 @@ fn default_port_for_proto(proto: Proto) -> u16 {
 #[derive(Clone, Debug)]
 pub struct ModbusUri {
     pub proto: Proto,
+    /// Host name for TCP, or the serial device path for RTU
     pub host: String,
-    pub port: u16,
+    /// TCP port, or the baud rate for RTU
+    pub port: u32,
+}
+
+impl ModbusUri {
+    /// RTU URIs name a device path rather than a host, e.g. `rtu:///dev/ttyUSB0:19200`, so they can't go through `Uri`.
+    fn parse_rtu(s: &str, path: &str) -> Result<ModbusUri, UriError> {
+        let (device, port) = match path.rsplit_once(':') {
+            Some((device, baud)) if !baud.is_empty() && baud.bytes().all(|b| b.is_ascii_digit()) =>
+                (device, baud.parse::<u32>().map_err(UriError::BaudRate)?),
+            _ => (path, default_port_for_proto(Proto::Rtu)),
+        };
+        if device.is_empty() {
+            return Err(UriError::Missing(MissingComponent{uri: s.to_string(), missing: "device"}))
+        }
+        Ok(ModbusUri{proto: Proto::Rtu, host: device.to_string(), port})
+    }
 }
 
 impl TryFrom<Uri> for ModbusUri {
@@ -122,7 +143,7 @@ impl TryFrom<Uri> for ModbusUri {
         };
 
         let port = match uri.port() {
-            Some(port) => port.as_u16(),
+            Some(port) => port.as_u16().into(),
             None => default_port_for_proto(proto),
         };
         
@@ -136,6 +157,9 @@ impl FromStr for ModbusUri {
 
     #[inline]
     fn from_str<'a>(s: &str) -> Result<ModbusUri, UriError> {
+        if let Some(path) = s.strip_prefix("rtu://") {
+            return ModbusUri::parse_rtu(s, path);
+        }
         let uri = Uri::try_from(s.as_bytes())?;
 
         ModbusUri::try_from(uri)
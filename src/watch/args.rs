@@ -0,0 +1,113 @@
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use clap::Args;
+use crate::client::Table;
+
+/// Poll a range and report only the values that change
+#[derive(Args, Clone, Debug)]
+pub struct WatchArgs {
+    /// table to watch
+    #[clap(value_enum)]
+    pub table: Table,
+
+    /// starting address
+    #[clap(value_parser)]
+    pub address: u16,
+
+    /// number of addresses to watch, up to 2000 coils or inputs, or 125 registers
+    #[clap(value_parser = clap::value_parser!(u16).range(1..2001))]
+    pub quantity: u16,
+
+    /// time between polls, e.g. 500ms or 1m
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "1s")]
+    pub interval: Duration,
+
+    /// how far a register has to move from its last reported value to be reported again.
+    /// Either absolute, e.g. 5, or a percentage of the last reported value, e.g. 2%. Ignored for coils and inputs
+    #[clap(long, value_parser, default_value = "0")]
+    pub deadband: Deadband,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Deadband {
+    Absolute(f64),
+    Percent(f64),
+}
+
+impl Deadband {
+    /// Whether a register moving from `last` to `value` falls outside of the deadband.
+    pub fn exceeded(&self, last: u16, value: u16) -> bool {
+        let change = (f64::from(value) - f64::from(last)).abs();
+        match *self {
+            Deadband::Absolute(band) => change > band,
+            Deadband::Percent(percent) => change > f64::from(last) * percent / 100.0,
+        }
+    }
+}
+
+impl FromStr for Deadband {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Deadband, Self::Err> {
+        let (number, percent) = match s.strip_suffix('%') {
+            Some(number) => (number, true),
+            None => (s, false),
+        };
+        let band: f64 = number.trim()
+            .parse()
+            .map_err(|e| format!("invalid deadband '{}': {}", s, e))?;
+        if band < 0.0 {
+            return Err(format!("invalid deadband '{}': must not be negative", s));
+        }
+        Ok(if percent { Deadband::Percent(band) } else { Deadband::Absolute(band) })
+    }
+}
+
+impl fmt::Display for Deadband {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Deadband::Absolute(band) => write!(f, "{}", band),
+            Deadband::Percent(band) => write!(f, "{}%", band),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_absolute_and_percent() {
+        assert_eq!("5".parse::<Deadband>(), Ok(Deadband::Absolute(5.0)));
+        assert_eq!("2.5%".parse::<Deadband>(), Ok(Deadband::Percent(2.5)));
+        assert!("-1".parse::<Deadband>().is_err());
+        assert!("five%".parse::<Deadband>().is_err());
+    }
+
+    #[test]
+    fn absolute_band_excludes_its_edge() {
+        let band = Deadband::Absolute(5.0);
+        assert!(!band.exceeded(100, 105));
+        assert!(!band.exceeded(100, 95));
+        assert!(band.exceeded(100, 106));
+        assert!(band.exceeded(100, 94));
+    }
+
+    #[test]
+    fn percent_band_scales_with_last_value() {
+        let band = Deadband::Percent(2.0);
+        assert!(!band.exceeded(500, 510));
+        assert!(band.exceeded(500, 511));
+        // Any change away from zero is reported.
+        assert!(band.exceeded(0, 1));
+    }
+
+    #[test]
+    fn zero_band_reports_every_change() {
+        let band: Deadband = "0".parse().unwrap();
+        assert!(!band.exceeded(7, 7));
+        assert!(band.exceeded(7, 8));
+    }
+}
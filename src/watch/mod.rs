@@ -0,0 +1,66 @@
+use std::time::SystemTime;
+use anyhow::{anyhow, Error};
+use tokio::time::MissedTickBehavior;
+
+use crate::client::ReaderExt;
+use crate::output::Output;
+
+pub mod args;
+
+/// Poll a range forever, writing a row for every value that changed since it was last reported.
+/// The first poll only establishes the baseline. Failed polls are reported and skipped.
+pub async fn watch_action(client: &mut dyn ReaderExt, args: args::WatchArgs, output: &mut dyn Output) -> Result<(), Error> {
+    args.table.check_range(args.address, args.quantity).map_err(|e| anyhow!(e))?;
+    let columns = vec![
+        "timestamp".to_string(),
+        "address".to_string(),
+        "previous".to_string(),
+        "value".to_string(),
+    ];
+    output.write_header(&columns)?;
+
+    let mut interval = tokio::time::interval(args.interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    // Values as they were last reported, which the deadband is measured against.
+    let mut reported: Option<Vec<u16>> = None;
+    loop {
+        interval.tick().await;
+
+        let timestamp = humantime::format_rfc3339_millis(SystemTime::now()).to_string();
+        let values = match client.read_table(args.table, args.address, args.quantity).await {
+            Ok(values) => values,
+            Err(e) => {
+                eprintln!("{} poll of {} failed: {}", timestamp, args.table, e);
+                continue;
+            },
+        };
+
+        let last = match reported.as_mut() {
+            Some(last) => last,
+            None => {
+                reported = Some(values);
+                continue;
+            },
+        };
+
+        let mut rows: Vec<Vec<String>> = vec![];
+        for (i, (last, &value)) in last.iter_mut().zip(values.iter()).enumerate() {
+            let changed = if args.table.is_bits() {
+                *last != value
+            } else {
+                *last != value && args.deadband.exceeded(*last, value)
+            };
+            if changed {
+                rows.push(vec![
+                    timestamp.clone(),
+                    (usize::from(args.address) + i).to_string(),
+                    args.table.format_value(*last),
+                    args.table.format_value(value),
+                ]);
+                *last = value;
+            }
+        }
+        output.write_rows(&rows)?;
+    }
+}